@@ -0,0 +1,304 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use m5conv::{
+    convert, well_name, M5File, OutputOptions, OutputRecord, ParseOptions, Wavelength,
+    DEFAULT_BUFFER_SIZE,
+};
+
+const PLATES: usize = 200;
+const READS: usize = 10;
+
+/// Blocks at which the rayon feature starts parsing them in parallel
+#[cfg(feature = "rayon")]
+const PARALLEL_BLOCK_THRESHOLD: usize = 16;
+
+/// A kinetic fluorescence export of `plates` full 96-well plates, [`READS`] reads each
+fn export(plates: usize) -> String {
+    let mut text = format!("##BLOCKS= {}\n", plates);
+    for plate in 0..plates {
+        writeln!(
+            text,
+            "Plate:\tP{}\t1.3\tPlateFormat\tKinetic\tFluorescence\tRaw\t\t\t{}\t\t\t\t\t\t1\t535 \t1\t12\t96\t485 \t\t\t\t\t\t\t\t\t1\t8\t",
+            plate, READS
+        )
+        .unwrap();
+        text.push_str("Time(hh:mm:ss)\tTemperature(°C)\t");
+        for col in 1..=12 {
+            write!(text, "{}\t", col).unwrap();
+        }
+        text.push('\n');
+        for read in 0..READS {
+            for row in 0..8 {
+                match row {
+                    0 => write!(text, "00:{:02}:00\t25.{}\t", read, read % 10).unwrap(),
+                    _ => text.push_str("\t\t"),
+                }
+                for col in 0..12 {
+                    let value = read * 1000 + row * 12 + col;
+                    write!(text, "{}.0000\t", value).unwrap();
+                }
+                text.push('\n');
+            }
+            text.push('\n');
+        }
+        text.push_str("~End\n");
+    }
+
+    text
+}
+
+/// Parse and write CSV as [`convert`] does, but serializing an [`OutputRecord`] per row
+/// rather than writing string arrays
+fn convert_records(text: &str, parse_opts: &ParseOptions) {
+    let mut wtr = csv::Writer::from_writer(io::sink());
+    M5File::for_each_read(text.as_bytes(), parse_opts, |settings, read_info, wells| {
+        for well in &wells {
+            let name = well_name(well.well);
+            let (excitation, emission) = match well.wavelength {
+                Wavelength::Fluorescence(ex, em) | Wavelength::Polarization { ex, em, .. } => {
+                    (Some(ex), Some(em))
+                }
+                _ => (None, None),
+            };
+            wtr.serialize(OutputRecord {
+                experiment: None,
+                block: None,
+                source_file: None,
+                plate: settings.name.clone(),
+                run: None,
+                row: name
+                    .trim_end_matches(|c: char| c.is_ascii_digit())
+                    .to_string(),
+                col: u16::from(well.well.1) + 1,
+                well: name,
+                point_row: None,
+                point_col: None,
+                time: read_info.get_time().map(|t| t.to_string()),
+                raw_time: None,
+                temperature: read_info.temp.raw(),
+                read_type: None,
+                read_pattern: None,
+                read_mode: settings.read_mode.name().to_string(),
+                excitation,
+                emission,
+                description: well.wavelength.to_string(),
+                value: well.value.num(),
+                raw_value: None,
+                value_unit: None,
+                status: Some(well.value.status().to_string()),
+                delay_us: None,
+                integration_us: None,
+                normalized: None,
+                corrected_od: None,
+                sample: None,
+            })?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    wtr.flush().unwrap();
+}
+
+fn bench(c: &mut Criterion) {
+    let text = export(PLATES);
+    let mut group = c.benchmark_group("convert");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.sample_size(20);
+    group.measurement_time(Duration::from_secs(10));
+
+    group.bench_function("parse", |b| {
+        b.iter(|| M5File::read_and_parse(text.as_bytes()).unwrap())
+    });
+    group.bench_function("parse + csv", |b| {
+        let (parse_opts, opts) = (ParseOptions::default(), OutputOptions::default());
+        b.iter(|| convert(text.as_bytes(), io::sink(), &parse_opts, &opts).unwrap())
+    });
+    // the row type CsvWriter doesn't use, to compare with its string arrays
+    group.bench_function("parse + csv (OutputRecord)", |b| {
+        let parse_opts = ParseOptions::default();
+        b.iter(|| convert_records(&text, &parse_opts))
+    });
+
+    group.finish();
+}
+
+/// Converting to a file on disk with the output buffered at [`DEFAULT_BUFFER_SIZE`] and at
+/// larger sizes; those only pay off where each write is expensive, as on a network
+/// filesystem, so point `TMPDIR` at one to see it
+fn bench_buffer_size(c: &mut Criterion) {
+    let text = export(PLATES);
+    let path = std::env::temp_dir().join("m5conv-bench.csv");
+    let mut group = c.benchmark_group("buffer size");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.sample_size(20);
+    group.measurement_time(Duration::from_secs(10));
+
+    for size in [DEFAULT_BUFFER_SIZE, 64 * 1024, 1024 * 1024] {
+        let opts = OutputOptions {
+            buffer_size: Some(size),
+            ..Default::default()
+        };
+        let parse_opts = ParseOptions::default();
+        group.bench_function(size.to_string(), |b| {
+            b.iter(|| {
+                let out = File::create(&path).unwrap();
+                convert(text.as_bytes(), out, &parse_opts, &opts).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+    let _ = std::fs::remove_file(&path);
+}
+
+/// Parsing an export with just enough blocks to be parsed in parallel, in a one-thread
+/// pool (which parses serially) against a four-thread one. With fewer cores than that, the
+/// parallel parse only shows what splitting up the file costs
+#[cfg(feature = "rayon")]
+fn bench_parallel(c: &mut Criterion) {
+    let text = export(PARALLEL_BLOCK_THRESHOLD);
+    let pool = |threads| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+    };
+    let mut group = c.benchmark_group("parallel threshold");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+
+    for (name, pool) in [("serial", pool(1)), ("parallel", pool(4))] {
+        group.bench_function(name, |b| {
+            b.iter(|| pool.install(|| M5File::read_and_parse(text.as_bytes()).unwrap()))
+        });
+    }
+
+    group.finish();
+}
+
+#[cfg(feature = "rayon")]
+criterion_group!(benches, bench, bench_buffer_size, bench_parallel);
+#[cfg(not(feature = "rayon"))]
+criterion_group!(benches, bench, bench_buffer_size);
+criterion_group! {
+    name = allocations;
+    config = Criterion::default().with_measurement(Allocations).without_plots();
+    targets = bench_allocations
+}
+criterion_main!(benches, allocations);
+
+/// Allocations (and reallocations) made since the start of the run
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// The system allocator, counting its allocations for [`Allocations`]
+struct CountingAlloc;
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Measures a benchmark by the allocations it makes rather than its time
+struct Allocations;
+
+impl Measurement for Allocations {
+    type Intermediate = u64;
+    type Value = u64;
+
+    fn start(&self) -> u64 {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+
+    fn end(&self, start: u64) -> u64 {
+        ALLOCATIONS.load(Ordering::Relaxed) - start
+    }
+
+    fn add(&self, a: &u64, b: &u64) -> u64 {
+        a + b
+    }
+
+    fn zero(&self) -> u64 {
+        0
+    }
+
+    fn to_f64(&self, value: &u64) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        self
+    }
+}
+
+/// Counts as they are; the throughput of a benchmark counting the grid rows it goes
+/// through is its allocations per row
+impl ValueFormatter for Allocations {
+    fn scale_values(&self, _typical: f64, _values: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        let (n, unit) = match *throughput {
+            Throughput::Elements(rows) => (rows, "allocs/row"),
+            Throughput::Bytes(n) | Throughput::BytesDecimal(n) => (n, "allocs/B"),
+        };
+        for value in values {
+            *value /= n as f64;
+        }
+        unit
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+}
+
+/// Allocations per grid row parsing an export, against those made collecting the fields of
+/// each row into a `Vec`, as `parse_plate` did before parsing them in place
+fn bench_allocations(c: &mut Criterion<Allocations>) {
+    let text = export(PLATES / 10);
+    let rows = text
+        .lines()
+        .filter(|l| l.starts_with("\t\t") || l.starts_with("00:"))
+        .collect::<Vec<_>>();
+    let mut group = c.benchmark_group("allocations");
+    group.throughput(Throughput::Elements(rows.len() as u64));
+
+    group.bench_function("parse", |b| {
+        b.iter(|| M5File::read_and_parse(text.as_bytes()).unwrap())
+    });
+    group.bench_function("collect rows", |b| {
+        b.iter(|| {
+            for row in &rows {
+                criterion::black_box(row.split('\t').collect::<Vec<_>>());
+            }
+        })
+    });
+
+    group.finish();
+}
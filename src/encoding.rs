@@ -0,0 +1,84 @@
+use encoding_rs::{Encoding, MACINTOSH, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/// Guess the text encoding of an export from its first few hundred (or thousand) bytes.
+///
+/// A byte order mark wins if there is one. Otherwise the degree symbol of the first plate's
+/// `Temperature(°C)` header tells MacRoman (`A1`), Windows-1252 (`B0`), and UTF-8 (`C2 B0`)
+/// apart. Falls back to MacRoman, which is what older Softmax versions write.
+pub fn detect_encoding(peek: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(peek) {
+        return encoding;
+    }
+    if let Some(encoding) = detect_utf16(peek) {
+        return encoding;
+    }
+
+    const HEADER: &[u8] = b"Temperature(";
+    let degree = peek
+        .windows(HEADER.len())
+        .position(|w| w == HEADER)
+        .map(|i| &peek[i + HEADER.len()..]);
+
+    match degree {
+        Some([0xA1, b'C' | b'F', ..]) => MACINTOSH,
+        Some([0xB0, b'C' | b'F', ..]) => WINDOWS_1252,
+        Some([0xC2, 0xB0, b'C' | b'F', ..]) => UTF_8,
+        _ => MACINTOSH,
+    }
+}
+
+/// UTF-16 without a BOM: ASCII text leaves every other byte zero
+fn detect_utf16(peek: &[u8]) -> Option<&'static Encoding> {
+    let pairs = peek.chunks_exact(2).take(64);
+    let total = pairs.len();
+    if total < 4 {
+        return None;
+    }
+    let (mut even_zero, mut odd_zero) = (0, 0);
+    for pair in pairs {
+        even_zero += (pair[0] == 0) as usize;
+        odd_zero += (pair[1] == 0) as usize;
+    }
+
+    if odd_zero * 4 >= total * 3 && even_zero == 0 {
+        Some(UTF_16LE)
+    } else if even_zero * 4 >= total * 3 && odd_zero == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &[u8] = b"##BLOCKS= 1\nPlate:\tPlate1\n\tTemperature(";
+
+    fn header(degree: &[u8]) -> Vec<u8> {
+        [START, degree, b"C)\t1\t2\n"].concat()
+    }
+
+    #[test]
+    fn degree_sign() {
+        assert_eq!(detect_encoding(&header(&[0xA1])), MACINTOSH);
+        assert_eq!(detect_encoding(&header(&[0xB0])), WINDOWS_1252);
+        assert_eq!(detect_encoding(&header(&[0xC2, 0xB0])), UTF_8);
+        assert_eq!(detect_encoding(b"##BLOCKS= 1\n"), MACINTOSH);
+    }
+
+    #[test]
+    fn bom() {
+        let utf8 = [&[0xEF, 0xBB, 0xBF][..], &header(&[0xA1])].concat();
+        assert_eq!(detect_encoding(&utf8), UTF_8);
+        assert_eq!(detect_encoding(&[0xFF, 0xFE, b'#', 0]), UTF_16LE);
+    }
+
+    #[test]
+    fn utf16_without_bom() {
+        let le = START.iter().flat_map(|&b| [b, 0]).collect::<Vec<_>>();
+        let be = START.iter().flat_map(|&b| [0, b]).collect::<Vec<_>>();
+        assert_eq!(detect_encoding(&le), UTF_16LE);
+        assert_eq!(detect_encoding(&be), UTF_16BE);
+    }
+}
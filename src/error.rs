@@ -0,0 +1,162 @@
+use std::{
+    error::Error as StdError,
+    io::{self, BufRead, Read},
+};
+use thiserror::Error;
+
+use crate::m5::supported_plate_sizes;
+
+/// Failure to parse an export.
+///
+/// A failure inside a block is wrapped in [`M5Error::Context`] layers saying where it
+/// happened, so match on [`M5Error::root`] to find the failure itself. Failures without a
+/// variant of their own are kept as `Other`
+#[derive(Debug, Error)]
+pub enum M5Error {
+    /// no `##BLOCKS=` line within the preamble; holds the input's first non-blank line
+    /// (shortened), which is empty for an empty input
+    #[error("Missing ##BLOCKS= magic line; {}", match first_line.as_str() {
+        "" => "the input is empty".to_string(),
+        line => format!("the input starts with \"{}\"", line),
+    })]
+    MissingBlocksMagic { first_line: String },
+    #[error("Unsupported plate size {0} (supported: {})", supported_plate_sizes())]
+    UnsupportedPlateSize(u32),
+    #[error("Unsupported read mode: {0}")]
+    UnsupportedReadMode(String),
+    /// `line` is the last line of the input, counting from 1
+    #[error("Unexpected end of file after line {line}")]
+    UnexpectedEof { line: usize },
+    /// `line` counts from 1
+    #[error("Couldn't parse well value \"{cell}\" on line {line}")]
+    WellValueParse { line: usize, cell: String },
+    /// with `strict_geometry`, a read's well count (masked wells included) isn't its read
+    /// region's rows × cols × wavelengths
+    #[error("Read has {found} well values, but its plate settings give {expected}")]
+    WellCountMismatch { expected: usize, found: usize },
+    /// with `strict_geometry`, a read lists a well twice for the same wavelength. `line` is
+    /// the read's last line, counting from 1
+    #[error(
+        "Well {well} ({wavelength}) is listed more than once in the read ending on line {line}"
+    )]
+    DuplicateWell {
+        well: String,
+        wavelength: String,
+        line: usize,
+    },
+    /// with `strict_values`, a well's value isn't finite (`NaN` or `inf`). `line` is the
+    /// read's last line, counting from 1
+    #[error("Well {well} ({wavelength}) has a non-finite value in the read ending on line {line}")]
+    NonFiniteValue {
+        well: String,
+        wavelength: String,
+        line: usize,
+    },
+    /// holds the line found instead of `~End`
+    #[error("Expected block end line, got \"{0}\"")]
+    UnexpectedBlockEnd(String),
+    #[error("{context}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<M5Error>,
+    },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl M5Error {
+    /// The failure under any context layers
+    pub fn root(&self) -> &Self {
+        match self {
+            Self::Context { source, .. } => source.root(),
+            e => e,
+        }
+    }
+
+    /// Convert an error from the (anyhow based) parser internals, keeping its context as
+    /// `Context` layers. `line` is how far the input had been read, for any line number
+    /// the failure left unset
+    pub(crate) fn from_anyhow(e: anyhow::Error, line: usize) -> Self {
+        let contexts = e.chain().map(ToString::to_string).collect::<Vec<_>>();
+        let mut root = match e.downcast::<Self>() {
+            Ok(root) => root,
+            Err(e) => match e.downcast::<io::Error>() {
+                Ok(e) => Self::Io(e),
+                Err(e) => return Self::Other(e),
+            },
+        };
+        root.set_line(line);
+
+        // the root's own sources are at the end of the chain, after the context layers
+        let root_len = std::iter::successors(Some(&root as &dyn StdError), |&e| e.source()).count();
+        let outer = contexts.len().saturating_sub(root_len);
+
+        contexts[..outer]
+            .iter()
+            .rev()
+            .fold(root, |source, context| Self::Context {
+                context: context.clone(),
+                source: Box::new(source),
+            })
+    }
+
+    fn set_line(&mut self, at: usize) {
+        match self {
+            Self::Context { source, .. } => source.set_line(at),
+            Self::UnexpectedEof { line }
+            | Self::WellValueParse { line, .. }
+            | Self::DuplicateWell { line, .. }
+            | Self::NonFiniteValue { line, .. }
+                if *line == 0 =>
+            {
+                *line = at
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Counts the lines read through it, for the line numbers of errors
+pub(crate) struct LineCounter<R> {
+    inner: R,
+    lines: usize,
+}
+
+impl<R> LineCounter<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self { inner, lines: 0 }
+    }
+
+    pub(crate) fn line_count(&self) -> usize {
+        self.lines
+    }
+}
+
+impl<R: Read> Read for LineCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.lines += count_lines(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for LineCounter<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // already buffered, so this doesn't read anything
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.lines += count_lines(&buf[..amt.min(buf.len())]);
+        }
+        self.inner.consume(amt)
+    }
+}
+
+fn count_lines(buf: &[u8]) -> usize {
+    buf.iter().filter(|&&b| b == b'\n').count()
+}
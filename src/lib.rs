@@ -0,0 +1,37 @@
+//! Parser for Softmax M5(e) tab-delimited plate reader exports
+//!
+//! The main entrypoint is [`M5File::read_and_parse`], which takes any `BufRead`
+//! over the (already decoded) text of an export.
+
+mod encoding;
+mod error;
+mod m5;
+mod output;
+mod utils;
+mod warning;
+mod wells;
+
+pub use encoding::detect_encoding;
+pub use error::M5Error;
+pub use m5::{
+    read_to_blocks_magic, Block, CellValue, GroupBlock, M5File, NoteBlock, OdUnit, Orientation,
+    ParseOptions, PlateBlock, PlateSettings, PolarizationChannel, ReadInfo, ReadMode, ReadType,
+    TempUnit, UniqueReadInfo, Wavelength, WellRC, WellRecord, WellValue, OVERFLOW_SENTINELS,
+    PATHCHECK_NM,
+};
+pub use output::{
+    convert, write_csv, write_json, write_matrix, write_ndjson, write_stream, write_streams,
+    Column, CsvWriter, JsonWriter, KineticWideWriter, MatrixWriter, MultiWriter, NameTemplate,
+    OutputOptions, OutputRecord, QcWriter, ReadWriter, SortWriter, SplitWriter, SummaryWriter,
+    TimeFormat, TotalsWriter, WellCountWriter, WellWideWriter, WriterFactory, DEFAULT_BUFFER_SIZE,
+};
+#[cfg(feature = "msgpack")]
+pub use output::{write_msgpack, write_msgpack_stream, MsgpackWriter};
+#[cfg(feature = "parquet")]
+pub use output::{write_parquet, ParquetWriter};
+#[cfg(feature = "sqlite")]
+pub use output::{write_sqlite, SqliteWriter};
+#[cfg(feature = "xlsx")]
+pub use output::{write_xlsx, XlsxWriter};
+pub use warning::WarningSink;
+pub use wells::{parse_well_name, well_name, Layout, WellSelection};
@@ -1,83 +1,645 @@
-use std::{io::BufRead, str::FromStr};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    convert::TryFrom,
+    fmt,
+    io::{self, BufRead, BufReader, Cursor},
+    str::FromStr,
+};
 
-use crate::utils::rmap2;
+use crate::error::{LineCounter, M5Error};
+use crate::utils::{glob_match, rmap2};
+use crate::warning::{Warning, WarningSink};
+use crate::wells::well_name;
 use anyhow::{anyhow, bail, Context, Error, Result};
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
 use noisy_float::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
-pub(crate) struct M5File(pub(crate) Vec<PlateBlock>);
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct M5File {
+    pub blocks: Vec<Block>,
+    /// lines before the `##BLOCKS=` magic (of each export, for concatenated exports), such
+    /// as the instrument serial or software version, trimmed and without blank lines
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub metadata: Vec<String>,
+}
+
+/// Options controlling how plate data is parsed
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// emit a [`CellValue::Masked`] value for empty cells in the read region rather than
+    /// skipping them
+    pub emit_empty: bool,
+    /// keep the blocks parsed before the input ends early (e.g. a missing final `~End`),
+    /// with a warning, rather than failing
+    pub lenient: bool,
+    /// numbers use a decimal comma (`1,5`) rather than a point; safe since the export
+    /// itself is tab-delimited
+    pub decimal_comma: bool,
+    /// only keep plate blocks whose name matches one of these, or all if empty. A pattern
+    /// with `*` or `?` is a glob over the whole name, otherwise a substring. Other plates
+    /// are skipped without parsing their reads
+    pub plates: Vec<String>,
+    /// fail if a read doesn't have a value (or masked cell) for every well of its read
+    /// region and wavelength, e.g. from a truncated grid
+    pub strict_geometry: bool,
+    /// fail on a well value that parses as a number but isn't finite (`NaN` or `inf`),
+    /// rather than keeping it as [`CellValue::NonFinite`] with a warning
+    pub strict_values: bool,
+    /// how plate wells are laid out in the printed grids
+    pub orientation: Orientation,
+    /// shift the times of each plate's reads so its earliest read is at 0. This holds all
+    /// of a plate's reads until the last is parsed, to find the earliest; reads without a
+    /// time are left as they are
+    pub relative_time: bool,
+    /// plate size (in wells) to use for every plate instead of the one in its settings,
+    /// e.g. when a hand-edited export has it wrong or missing. Fails if a read region
+    /// doesn't fit on a plate of this size
+    pub plate_size: Option<u32>,
+    /// keep each well's value as written in the export, in [`WellValue::raw`]
+    pub raw_values: bool,
+    /// where warnings about the input are reported. Each parse reports a warning given once
+    /// per plate (e.g. an assumed OD unit) anew
+    pub warnings: WarningSink,
+}
+
+/// Layout of a read's grid: one printed line per plate row (the usual), or one per plate
+/// column, as some well scan exports print it.
+///
+/// This is only about where each well sits in the grid; the `read_pattern` of a well scan
+/// read is the pattern of points read within each well, and doesn't change it
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Orientation {
+    #[default]
+    RowMajor,
+    ColMajor,
+}
+
+impl FromStr for Orientation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "row-major" => Ok(Self::RowMajor),
+            "col-major" => Ok(Self::ColMajor),
+            _ => Err(anyhow!("Unknown grid orientation: {}", s)),
+        }
+    }
+}
+
+impl ParseOptions {
+    /// `s` with any decimal comma swapped for a point, ready for `str::parse`
+    fn number<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        if self.decimal_comma && s.contains(',') {
+            Cow::Owned(s.replace(',', "."))
+        } else {
+            Cow::Borrowed(s)
+        }
+    }
+
+    fn keeps_plate(&self, name: &str) -> bool {
+        self.plates.is_empty() || self.plates.iter().any(|p| plate_matches(p, name))
+    }
+
+    /// Drop the skipped plate blocks, warning about any pattern that matched no plate
+    fn drop_skipped_plates(&self, blocks: &mut Vec<Block>) {
+        for pattern in &self.plates {
+            let found = blocks
+                .iter()
+                .any(|b| matches!(b, Block::Plate(p) if plate_matches(pattern, &p.settings.name)));
+            if !found {
+                self.warnings.warn(Warning::UnmatchedPlate {
+                    pattern: pattern.clone(),
+                });
+            }
+        }
+
+        blocks.retain(|b| !matches!(b, Block::Plate(p) if !self.keeps_plate(&p.settings.name)));
+    }
+}
+
+fn plate_matches(pattern: &str, name: &str) -> bool {
+    if pattern.contains(['*', '?']) {
+        glob_match(pattern, name)
+    } else {
+        name.contains(pattern)
+    }
+}
 
 impl M5File {
-    pub(crate) fn read_and_parse<R: BufRead>(mut rdr: R) -> Result<Self> {
+    pub fn read_and_parse<R: BufRead>(rdr: R) -> Result<Self, M5Error> {
+        Self::read_and_parse_with(rdr, &ParseOptions::default())
+    }
+
+    /// Parse an export already in memory, decoding it from `encoding` (see
+    /// [`detect_encoding`](crate::detect_encoding)); a BOM overrides the given encoding
+    pub fn from_bytes(data: &[u8], encoding: &'static Encoding) -> Result<Self, M5Error> {
+        let decoder = DecodeReaderBytesBuilder::new()
+            .encoding(Some(encoding))
+            .bom_override(true)
+            .build(Cursor::new(data));
+        Self::read_and_parse(BufReader::new(decoder))
+    }
+
+    pub fn read_and_parse_with<R: BufRead>(rdr: R, opts: &ParseOptions) -> Result<Self, M5Error> {
+        let mut rdr = LineCounter::new(rdr);
+        Self::parse_with(&mut rdr, opts).map_err(|e| M5Error::from_anyhow(e, rdr.line_count()))
+    }
+
+    fn parse_with<R: BufRead>(rdr: &mut LineCounter<R>, opts: &ParseOptions) -> Result<Self> {
+        opts.warnings.start_input();
         let mut buf = String::with_capacity(0x100);
+        let (mut block_count, mut metadata) = read_block_count(rdr, &mut buf)?;
+        let mut blocks = Vec::with_capacity(block_count as usize);
 
-        rdr.read_line(&mut buf).context("reading block count")?;
-        let block_count = get_block_count(&buf).context("parsing initial blocks count")?;
-        buf.clear();
+        // concatenated exports each start with their own `##BLOCKS=` line
+        for experiment in 1.. {
+            blocks.extend(parse_export(rdr, &mut buf, experiment, block_count, opts)?);
+
+            match read_next_block_count(rdr, &mut buf)? {
+                Some((count, preamble)) => {
+                    block_count = count;
+                    metadata.extend(preamble);
+                }
+                None => break,
+            }
+        }
+        opts.drop_skipped_plates(&mut blocks);
+
+        Ok(Self { blocks, metadata })
+    }
+
+    /// Parse the file one plate read at a time, handing each read to `f` as soon as it's
+    /// parsed instead of keeping it in memory.
+    ///
+    /// Returns the rest of the file: any group and note blocks, along with the settings
+    /// of each plate block, whose reads are left empty. An error from `f` is passed on as
+    /// [`M5Error::Other`] (or [`M5Error::Io`]), in the context of the block
+    pub fn for_each_read<R, F>(rdr: R, opts: &ParseOptions, f: F) -> Result<Self, M5Error>
+    where
+        R: BufRead,
+        F: FnMut(&PlateSettings, ReadInfo, Vec<WellValue>) -> Result<()>,
+    {
+        let mut rdr = LineCounter::new(rdr);
+        Self::parse_each(&mut rdr, opts, f).map_err(|e| M5Error::from_anyhow(e, rdr.line_count()))
+    }
+
+    fn parse_each<R, F>(rdr: &mut LineCounter<R>, opts: &ParseOptions, mut f: F) -> Result<Self>
+    where
+        R: BufRead,
+        F: FnMut(&PlateSettings, ReadInfo, Vec<WellValue>) -> Result<()>,
+    {
+        opts.warnings.start_input();
+        let mut buf = String::with_capacity(0x100);
+        let (mut block_count, mut metadata) = read_block_count(rdr, &mut buf)?;
+        let mut blocks = Vec::with_capacity(block_count as usize);
+
+        for experiment in 1.. {
+            // reads of a block cut off by the end of the input have already gone to `f`,
+            // so a lenient parse keeps them rather than re-reading anything
+            let parsed = parse_blocks(
+                rdr,
+                &mut buf,
+                experiment,
+                block_count,
+                opts,
+                |rdr, buf, pos| {
+                    Block::from_rdr_with(rdr, buf, |rdr, buf| {
+                        let sections = PlateBlock::read_each(rdr, buf, pos, opts, &mut f)?;
+                        let data = sections.iter().map(|_| Vec::new()).collect();
+                        Ok(Block::Plate(PlateBlock::from_sections(sections, data)))
+                    })
+                },
+            )?;
+            blocks.extend(parsed);
+
+            match read_next_block_count(rdr, &mut buf)? {
+                Some((count, preamble)) => {
+                    block_count = count;
+                    metadata.extend(preamble);
+                }
+                None => break,
+            }
+        }
+        opts.drop_skipped_plates(&mut blocks);
+
+        Ok(Self { blocks, metadata })
+    }
+
+    /// Lines of the preamble before the `##BLOCKS=` magic, if the export has one
+    pub fn metadata(&self) -> &[String] {
+        &self.metadata
+    }
+
+    /// Plate blocks of the file, skipping any group or note blocks. Each further read
+    /// section of a block comes right after it, as a plate of its own
+    pub fn plates(&self) -> impl Iterator<Item = &PlateBlock> {
+        self.blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Plate(plate) => Some(plate),
+                _ => None,
+            })
+            .flat_map(|plate| std::iter::once(plate).chain(&plate.sections))
+    }
+
+    /// Raw text of each note block in the file
+    pub fn notes(&self) -> impl Iterator<Item = &str> {
+        self.blocks.iter().filter_map(|block| match block {
+            Block::Note(note) => Some(note.text.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Every well value of the file, in order, with the plate and read it belongs to
+    pub fn wells(&self) -> impl Iterator<Item = WellRecord<'_>> {
+        self.plates().flat_map(|plate| {
+            plate.data.iter().flat_map(move |(read_info, wells)| {
+                wells.iter().map(move |well| WellRecord {
+                    settings: &plate.settings,
+                    read_info,
+                    well,
+                })
+            })
+        })
+    }
 
-        (0..block_count)
-            .map(|i| {
-                PlateBlock::from_rdr(&mut rdr, &mut buf)
-                    .with_context(|| anyhow!("parsing block {}", i + 1))
+    /// [`M5File::plates`], by value
+    pub fn into_plates(self) -> impl Iterator<Item = PlateBlock> {
+        self.blocks
+            .into_iter()
+            .filter_map(|block| match block {
+                Block::Plate(plate) => Some(plate),
+                _ => None,
+            })
+            .flat_map(|mut plate| {
+                let sections = std::mem::take(&mut plate.sections);
+                std::iter::once(plate).chain(sections)
             })
-            .collect::<Result<_, _>>()
-            .map(Self)
     }
 }
 
+/// One well value of an [`M5File`], from [`M5File::wells`]
+#[derive(Debug, Copy, Clone)]
+pub struct WellRecord<'a> {
+    pub settings: &'a PlateSettings,
+    pub read_info: &'a ReadInfo,
+    pub well: &'a WellValue,
+}
+
+/// A single `~End` terminated block of an export
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Block {
+    Plate(PlateBlock),
+    Group(GroupBlock),
+    Note(NoteBlock),
+}
+
+impl Block {
+    fn from_rdr(
+        rdr: &mut dyn BufRead,
+        buf: &mut String,
+        pos: BlockPos,
+        opts: &ParseOptions,
+    ) -> Result<Self> {
+        Self::from_rdr_with(rdr, buf, |rdr, buf| {
+            PlateBlock::from_rdr(rdr, buf, pos, opts).map(Self::Plate)
+        })
+    }
+
+    /// `plate` parses the rest of a plate block, once its settings row is in `buf`
+    fn from_rdr_with<F>(rdr: &mut dyn BufRead, buf: &mut String, plate: F) -> Result<Self>
+    where
+        F: FnOnce(&mut dyn BufRead, &mut String) -> Result<Self>,
+    {
+        read_data_line(rdr, buf).context("reading block header row")?;
+        let kind = buf.split('\t').next().unwrap_or_default().trim();
+
+        let block = match kind {
+            "Plate:" => plate(rdr, buf)?,
+            "Group:" => {
+                let name = block_name(buf);
+                buf.clear();
+                let text = read_to_block_end(rdr, buf).context("reading group block")?;
+                Self::Group(GroupBlock { name, text })
+            }
+            "Note:" => {
+                let name = block_name(buf);
+                buf.clear();
+                let text = read_to_block_end(rdr, buf).context("reading note block")?;
+                Self::Note(NoteBlock { name, text })
+            }
+            _ => bail!("Unknown block type \"{}\"", kind),
+        };
+
+        Ok(block)
+    }
+}
+
+/// Group (sample table) block, kept as raw text for now
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GroupBlock {
+    pub name: String,
+    /// raw lines of the block, excluding the header and `~End` lines
+    pub text: String,
+}
+
+impl GroupBlock {
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.text.lines()
+    }
+}
+
+/// Free-text note block
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NoteBlock {
+    pub name: String,
+    /// raw lines of the block, excluding the header and `~End` lines
+    pub text: String,
+}
+
+impl NoteBlock {
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.text.lines()
+    }
+}
+
+fn block_name(header: &str) -> String {
+    header
+        .split('\t')
+        .nth(1)
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// If `line` ends a block, anything after `~End` in further tab-delimited fields (e.g. a
+/// checksum), trimmed
+fn block_end(line: &str) -> Option<&str> {
+    let (first, rest) = line.split_once('\t').unwrap_or((line, ""));
+    Some(rest.trim()).filter(|_| first.trim() == "~End")
+}
+
+/// Read past the rest of a block, including its `~End` line
+fn skip_to_block_end(rdr: &mut dyn BufRead, buf: &mut String) -> Result<()> {
+    loop {
+        read_data_line(rdr, buf)?;
+        if block_end(buf).is_some() {
+            buf.clear();
+            return Ok(());
+        }
+        buf.clear();
+    }
+}
+
+/// Collect the raw lines of a block up to (not including) its `~End` line. Blank lines
+/// are kept, since only `~End` terminates a block
+fn read_to_block_end(rdr: &mut dyn BufRead, buf: &mut String) -> Result<String> {
+    let mut text = String::new();
+
+    loop {
+        read_data_line(rdr, buf)?;
+        if block_end(buf).is_some() {
+            buf.clear();
+            return Ok(text);
+        }
+        text.push_str(buf);
+        buf.clear();
+    }
+}
+
+/// Receives each plate read as it's parsed
+type ReadCallback<'a> = dyn FnMut(&PlateSettings, ReadInfo, Vec<WellValue>) -> Result<()> + 'a;
+
 #[derive(Debug)]
-pub(crate) struct PlateBlock {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlateBlock {
     pub settings: PlateSettings,
     pub data: Vec<(ReadInfo, Vec<WellValue>)>,
+    /// any further read sections of the same plate in this block, each with its own
+    /// settings (e.g. a fluorescence read after an absorbance one). [`M5File::plates`]
+    /// lists them as plates of their own
+    pub sections: Vec<PlateBlock>,
 }
 
 impl PlateBlock {
-    fn from_rdr(mut rdr: &mut dyn BufRead, buf: &mut String) -> Result<Self> {
-        // read and parse plate settings row
-        rdr.read_line(buf).context("reading plate info row")?;
-        let settings = PlateSettings::parse(buf).context("parsing plate info")?;
+    /// Expects `buf` to already hold the block's plate settings row
+    fn from_rdr(
+        rdr: &mut dyn BufRead,
+        buf: &mut String,
+        pos: BlockPos,
+        opts: &ParseOptions,
+    ) -> Result<Self> {
+        let mut data = vec![Vec::new()];
+        let sections = Self::read_each(rdr, buf, pos, opts, &mut |settings, read_info, wells| {
+            data.resize_with(settings.section, Vec::new);
+            data[settings.section - 1].push((read_info, wells));
+            Ok(())
+        })?;
+        data.resize_with(sections.len(), Vec::new);
+
+        Ok(Self::from_sections(sections, data))
+    }
+
+    /// The first section holds the rest, each getting the reads at its index of `data`
+    fn from_sections(
+        sections: Vec<PlateSettings>,
+        data: Vec<Vec<(ReadInfo, Vec<WellValue>)>>,
+    ) -> Self {
+        let mut sections = sections.into_iter().zip(data).map(|(settings, data)| Self {
+            settings,
+            data,
+            sections: Vec::new(),
+        });
+        let mut first = sections
+            .next()
+            .expect("a plate block has at least one section");
+        first.sections = sections.collect();
+        first
+    }
+
+    /// Parse the block's reads, passing each one to `f` as it's read, along with the
+    /// settings of its section. Returns the settings of each section in turn.
+    ///
+    /// A block usually has one section, ended by `~End`. Another plate settings row in
+    /// place of `~End` starts a further section, with its own header row and reads
+    fn read_each(
+        rdr: &mut dyn BufRead,
+        buf: &mut String,
+        pos: BlockPos,
+        opts: &ParseOptions,
+        f: &mut ReadCallback,
+    ) -> Result<Vec<PlateSettings>> {
+        let mut sections = Vec::new();
+
+        loop {
+            let section = sections.len() + 1;
+            let parsed = Self::read_section(rdr, buf, pos, section, opts, f);
+            let (settings, more) = match section {
+                1 => parsed?,
+                _ => parsed.with_context(|| anyhow!("parsing read section {}", section))?,
+            };
+            sections.push(settings);
+            if !more {
+                return Ok(sections);
+            }
+        }
+    }
+
+    /// One section of a plate block, from its settings row in `buf`. Returns the settings,
+    /// and if another section follows, whose settings row is then left in `buf`
+    fn read_section(
+        mut rdr: &mut dyn BufRead,
+        buf: &mut String,
+        pos: BlockPos,
+        section: usize,
+        opts: &ParseOptions,
+        f: &mut ReadCallback,
+    ) -> Result<(PlateSettings, bool)> {
+        // parse plate settings row
+        let mut settings =
+            PlateSettings::parse(buf, opts.plate_size).context("parsing plate info")?;
+        settings.experiment = pos.experiment;
+        settings.block = pos.block;
+        settings.section = section;
         buf.clear();
+        if !opts.keeps_plate(&settings.name) {
+            skip_to_block_end(rdr, buf).context("skipping plate block")?;
+            return Ok((settings, false));
+        }
+        if settings.read_mode == ReadMode::Absorbance && settings.info.od_unit.is_none() {
+            // once per plate name, rather than for every block of a plate
+            opts.warnings.warn_once(Warning::AssumedOdUnit {
+                plate: settings.name.clone(),
+            });
+            settings.info.od_unit = Some(OdUnit::Od);
+        }
         // read time / temp / col headers line
         // TODO: more validation of this row? The first column seems to change based on ReadType
-        rdr.read_line(buf)
-            .context("reading temp. and plate col header line")?;
-        match buf.split('\t').nth(1) {
-            Some("Temperature(°C)") => (),
-            Some(unk) => bail!("Unknown/unsupported temperature unit: {}", unk),
+        read_data_line(rdr, buf).context("reading temp. and plate col header line")?;
+        match buf.split('\t').nth(1).map(str::trim) {
+            Some(col) => match is_temp_header(col) {
+                Some(unit) => settings.temp_unit = unit,
+                // an unknown degree symbol means the input was decoded with the wrong encoding
+                None if col.starts_with("Temperature(")
+                    && (col.ends_with("C)") || col.ends_with("F)")) =>
+                {
+                    bail!(
+                        "Unexpected temperature header {}; check the input text encoding",
+                        col
+                    )
+                }
+                None => bail!("Unknown/unsupported temperature unit: {}", col),
+            },
             None => bail!("Couldn't read temperature and plate headers:\n{}", buf),
         }
+        let grid = Grid::from_header(buf, &settings, opts.orientation)?;
+        settings.info.scan_points = grid.points;
         buf.clear();
 
-        // read each single read of a plate
-        let mut data = Vec::with_capacity(settings.info.reads);
+        // the geometry check counts masked cells, so keep them until it's done
+        let strict_opts;
+        let read_opts = if opts.strict_geometry && !opts.emit_empty {
+            strict_opts = ParseOptions {
+                emit_empty: true,
+                ..opts.clone()
+            };
+            &strict_opts
+        } else {
+            opts
+        };
+
+        // read each single read of a plate; kinetic blocks store one grid per timepoint,
+        // with that timepoint's time and temperature in the first two columns of the
+        // grid's first row
+        let mut held = Vec::new();
         for i in 0..settings.info.reads {
-            let read_output = parse_plate(&mut rdr, buf, &settings)
-                .with_context(|| anyhow!("parsing plate read {}", i + 1))?;
-            data.push(read_output)
+            let (read_info, wells) = match settings.read_type {
+                ReadType::Spectrum => parse_spectrum(&mut rdr, buf, &settings, read_opts),
+                _ => parse_plate(&mut rdr, buf, &settings, grid, read_opts),
+            }
+            .and_then(|(mut read_info, wells)| {
+                check_duplicates(&settings, i + 1, &wells, opts)?;
+                check_values(&settings, i + 1, &wells, opts)?;
+                let wells = check_geometry(&settings, opts, wells)?;
+                if !opts.emit_empty {
+                    read_info.empty_cells =
+                        settings.info.total_wells_read().saturating_sub(wells.len());
+                }
+                Ok((read_info, wells))
+            })
+            .with_context(|| anyhow!("parsing plate read {}", i + 1))?;
+            match opts.relative_time {
+                true => held.push((read_info, wells)),
+                false => f(&settings, read_info, wells)?,
+            }
+        }
+        let start = held.iter().filter_map(|(info, _)| info.get_time()).min();
+        for (mut read_info, wells) in held {
+            if let (UniqueReadInfo::Time(time, _), Some(start)) = (&mut read_info.unique, start) {
+                *time -= start;
+            }
+            f(&settings, read_info, wells)?;
         }
         buf.clear();
 
-        rdr.read_line(buf).context("reading end block magic line")?;
-        if buf.trim() != "~End" {
-            bail!("Expected block end line, got \"{}\"", buf);
+        read_line(rdr, buf).context("reading end block magic line")?;
+        if buf.starts_with("Plate:") {
+            return Ok((settings, true));
+        }
+        if buf.is_empty() && opts.lenient {
+            opts.warnings.warn(Warning::MissingBlockEnd {
+                plate: settings.name.clone(),
+            });
+        } else {
+            match block_end(buf) {
+                Some("") => (),
+                Some(fields) => settings.end_fields = Some(fields.to_string()),
+                None => bail!(M5Error::UnexpectedBlockEnd(buf.trim_end().to_string())),
+            }
         }
         buf.clear();
 
-        Ok(Self { settings, data })
+        Ok((settings, false))
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct PlateSettings {
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlateSettings {
     pub name: String,
     pub read_type: ReadType,
     pub read_mode: ReadMode,
-    // read_pattern: String, WellScan Only [idx 10]
+    /// pattern of the points read in each well (e.g. `Fill` or `Cross`); well scan reads
+    /// only
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub read_pattern: Option<String>,
     info: PlateInfo,
+    /// 1-based index of the export holding the block, for concatenated exports; filled in
+    /// by the block parser
+    #[cfg_attr(feature = "serde", serde(default = "first_experiment"))]
+    experiment: usize,
+    /// 1-based position of the block in its export, filled in by the block parser
+    block: usize,
+    /// 1-based position of the read section in its block, filled in by the block parser
+    section: usize,
+    /// unit of the temperatures in the export, filled in from the temperature header
+    temp_unit: TempUnit,
+    /// fields after `~End` on the block's last line, filled in once the block is read
+    #[cfg_attr(feature = "serde", serde(default))]
+    end_fields: Option<String>,
 }
 
 impl PlateSettings {
-    pub(crate) fn parse(s: &str) -> Result<Self> {
+    /// `plate_size` overrides the settings' own plate size
+    pub(crate) fn parse(s: &str, plate_size: Option<u32>) -> Result<Self> {
         let info = s.split('\t').map(str::trim).collect::<Vec<_>>();
         if info.len() < 6 {
             bail!("Missing basic plate setting info:\n{:#?}", info);
@@ -86,20 +648,120 @@ impl PlateSettings {
         let name = info[1].to_string();
         let read_type = ReadType::from_str(info[4])?;
         let read_mode = ReadMode::from_str(info[5])?;
+        let read_pattern = match read_type {
+            ReadType::WellScan => info
+                .get(10)
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string()),
+            _ => None,
+        };
         let unique_data = &info[6..];
-        let info = PlateInfo::from_text(read_type, read_mode, unique_data)
+        let info = PlateInfo::from_text(read_type, read_mode, unique_data, plate_size)
             .with_context(|| anyhow!("bad info? {:#?}", unique_data))?;
 
         Ok(Self {
             name,
             read_type,
             read_mode,
+            read_pattern,
             info,
+            experiment: 1,
+            block: 0,
+            section: 1,
+            temp_unit: TempUnit::Celsius,
+            end_fields: None,
         })
     }
+
+    /// 1-based index of the export holding the plate, in a file of several exports
+    /// concatenated together (each with its own `##BLOCKS=` line); 1 for a single export
+    pub fn experiment(&self) -> usize {
+        self.experiment
+    }
+
+    /// 1-based position of the plate's block in its export, counting group and note blocks
+    pub fn block_index(&self) -> usize {
+        self.block
+    }
+
+    /// 1-based position of the plate's read section in its block; only blocks holding
+    /// several reads of the same plate have more than one
+    pub fn section(&self) -> usize {
+        self.section
+    }
+
+    /// Unit of the temperatures as written in the export; parsed temperatures are always
+    /// converted to degrees Celsius
+    pub fn temp_unit(&self) -> TempUnit {
+        self.temp_unit
+    }
+
+    /// Anything after `~End` on the block's last line, such as a checksum or label. This is
+    /// only known once the whole block is read, so the settings passed along with each
+    /// read while parsing don't have it
+    pub fn end_fields(&self) -> Option<&str> {
+        self.end_fields.as_deref()
+    }
+
+    /// Number of wells on the plate (e.g. 96 or 384)
+    pub fn plate_size(&self) -> u32 {
+        self.info.plate_size
+    }
+
+    /// Number of plate rows covered by the read
+    pub fn rows(&self) -> u8 {
+        self.info.row_span
+    }
+
+    /// Number of plate columns covered by the read
+    pub fn cols(&self) -> u8 {
+        self.info.col_span
+    }
+
+    /// First plate row of the read, as listed in the settings row
+    pub fn row_start(&self) -> u8 {
+        self.info.row_start
+    }
+
+    /// First plate column of the read, as listed in the settings row
+    pub fn col_start(&self) -> u8 {
+        self.info.col_start
+    }
+
+    /// Number of reads of the plate stored in the block (timepoints for kinetic reads)
+    pub fn reads(&self) -> usize {
+        self.info.reads
+    }
+
+    pub fn wavelengths(&self) -> &[Wavelength] {
+        &self.info.wavelengths
+    }
+
+    /// Unit of the values as written in the export; absorbance reads only. A read whose
+    /// settings don't give it is taken to be in OD
+    pub fn od_unit(&self) -> Option<OdUnit> {
+        self.info.od_unit
+    }
+
+    /// Points per side of the square grid read in each well (e.g. 3 for 3 x 3 points); well
+    /// scan reads only
+    pub fn scan_points(&self) -> Option<u8> {
+        self.info.scan_points
+    }
+
+    /// Delay after the flash before measuring [us]; time-resolved reads only
+    pub fn delay_us(&self) -> Option<u32> {
+        self.info.delay_us
+    }
+
+    /// Length of the measurement window [us]; time-resolved reads only
+    pub fn integration_us(&self) -> Option<u32> {
+        self.info.integration_us
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct PlateInfo {
     plate_size: u32,
     row_start: u8,
@@ -108,23 +770,58 @@ struct PlateInfo {
     col_span: u8,
     reads: usize,
     wavelengths: Vec<Wavelength>,
+    delay_us: Option<u32>,
+    integration_us: Option<u32>,
+    /// unit of the values of an absorbance read, if its settings give a known one
+    #[cfg_attr(feature = "serde", serde(default))]
+    od_unit: Option<OdUnit>,
+    /// points per side of the square grid read in each well of a well scan, filled in from
+    /// the read's grid
+    #[cfg_attr(feature = "serde", serde(default))]
+    scan_points: Option<u8>,
+}
+
+/// The unit key of absorbance settings, 15 (after the row span)
+fn od_unit(keys: &[&str]) -> Option<OdUnit> {
+    keys.get(15)?.parse().ok()
 }
 
 impl PlateInfo {
-    fn from_text(read_type: ReadType, read_mode: ReadMode, keys: &[&str]) -> Result<Self> {
+    fn from_text(
+        read_type: ReadType,
+        read_mode: ReadMode,
+        keys: &[&str],
+        plate_size: Option<u32>,
+    ) -> Result<Self> {
+        // an overridden plate size isn't read from the keys, so may be missing there
+        let size_at = |i: usize| match plate_size {
+            Some(size) => Ok(size),
+            None => keys[i].parse::<u32>().context("plate size"),
+        };
+
         let info = match (read_type, read_mode) {
-            (ReadType::Endpoint, ReadMode::Absorbance) => {
+            (ReadType::Endpoint, ReadMode::Absorbance)
+            | (ReadType::Kinetic, ReadMode::Absorbance) => {
                 let reads = keys[2].parse()?;
                 let row_start = keys[13].parse()?;
                 let row_span = keys[14].parse()?;
                 let col_start = keys[10].parse()?;
                 let col_span = keys[11].parse()?;
-                let plate_size = keys[12].parse()?;
+                let plate_size = size_at(12)?;
                 let wave_no = keys[8].parse()?;
+                // an M5e PathCheck read lists its reference wavelengths after the sample
+                // ones, beyond the wavelength count, with a grid stripe of their own
                 let wavelengths = keys[9]
                     .split_whitespace()
-                    .take(wave_no)
-                    .map(|s| s.parse().map(Wavelength::Absorbance))
+                    .enumerate()
+                    .map(|(i, s)| s.parse().map(|nm| (i, nm)))
+                    .filter(|r| !matches!(r, Ok((i, nm)) if *i >= wave_no && !PATHCHECK_NM.contains(nm)))
+                    .map(|r| {
+                        r.map(|(i, nm)| match i < wave_no {
+                            true => Wavelength::Absorbance(nm),
+                            false => Wavelength::PathCheck(nm),
+                        })
+                    })
                     .collect::<Result<_, _>>()?;
 
                 Self {
@@ -135,25 +832,110 @@ impl PlateInfo {
                     col_span,
                     reads,
                     wavelengths,
+                    delay_us: None,
+                    integration_us: None,
+                    od_unit: od_unit(keys),
+                    scan_points: None,
                 }
             }
+            // FP reads use the fluorescence keys; the polarization channels aren't listed in
+            // them, but each ex/em pair's grid holds a parallel then a perpendicular stripe.
+            // TRF reads add the delay / integration time [us] after them, at 25 / 26
             (ReadType::Endpoint, ReadMode::Fluorescence)
-            | (ReadType::WellScan, ReadMode::Fluorescence) => {
+            | (ReadType::WellScan, ReadMode::Fluorescence)
+            | (ReadType::Kinetic, ReadMode::Fluorescence)
+            | (ReadType::Endpoint, ReadMode::FluorescencePolarization)
+            | (ReadType::Kinetic, ReadMode::FluorescencePolarization)
+            | (ReadType::Endpoint, ReadMode::TimeResolved)
+            | (ReadType::Kinetic, ReadMode::TimeResolved) => {
                 let reads = keys[3].parse().context("read no")?;
                 let row_start = keys[23].parse().context("row start")?;
                 let row_span = keys[24].parse().context("row span")?;
                 let col_start = keys[11].parse().context("col start")?;
                 let col_span = keys[12].parse().context("col span")?;
-                let plate_size = keys[13].parse().context("plate size")?;
+                let plate_size = size_at(13)?;
                 let wave_no = keys[9].parse().context("wave no")?;
-                let exs = keys[14].split_whitespace();
-                let ems = keys[10].split_whitespace();
-                let wavelengths = exs
-                    .zip(ems)
-                    .take(wave_no)
-                    .map(|(ex, em)| rmap2(ex.parse(), em.parse(), Wavelength::Fluorescence))
-                    .collect::<Result<_, _>>()
+                let exs = keys[14].split_whitespace().collect::<Vec<_>>();
+                let ems = keys[10].split_whitespace().collect::<Vec<_>>();
+                // an emission scan at a fixed excitation only lists the excitation once
+                let fixed_ex = exs.len() == 1;
+                if (exs.len() < wave_no && !fixed_ex) || ems.len() < wave_no {
+                    bail!(
+                        "Expected {} ex/em wavelength pairs, but found {} excitation and {} emission wavelengths",
+                        wave_no,
+                        exs.len(),
+                        ems.len()
+                    );
+                }
+                let pairs = (0..wave_no)
+                    .map(|i| (if fixed_ex { exs[0] } else { exs[i] }, ems[i]))
+                    .map(|(ex, em)| rmap2(ex.parse(), em.parse(), |ex, em| (ex, em)))
+                    .collect::<Result<Vec<_>, _>>()
                     .context("parsing ex/em wavelengths")?;
+                let wavelengths = match read_mode {
+                    ReadMode::FluorescencePolarization => pairs
+                        .into_iter()
+                        .flat_map(|(ex, em)| {
+                            PolarizationChannel::ALL
+                                .iter()
+                                .map(move |&channel| Wavelength::Polarization { ex, em, channel })
+                        })
+                        .collect(),
+                    _ => pairs
+                        .into_iter()
+                        .map(|(ex, em)| Wavelength::Fluorescence(ex, em))
+                        .collect(),
+                };
+                let (delay_us, integration_us) = match read_mode {
+                    ReadMode::TimeResolved => {
+                        let timing = |i: usize, name: &str| {
+                            keys.get(i)
+                                .ok_or_else(|| anyhow!("missing TRF {}", name))?
+                                .parse()
+                                .with_context(|| anyhow!("TRF {}", name))
+                        };
+                        (Some(timing(25, "delay")?), Some(timing(26, "integration")?))
+                    }
+                    _ => (None, None),
+                };
+
+                Self {
+                    plate_size,
+                    row_start,
+                    row_span,
+                    col_start,
+                    col_span,
+                    reads,
+                    wavelengths,
+                    delay_us,
+                    integration_us,
+                    od_unit: None,
+                    scan_points: None,
+                }
+            }
+            // keys as listed at `ReadType::Spectrum`
+            (ReadType::Spectrum, ReadMode::Absorbance) => {
+                let reads = keys[2].parse().context("read no")?;
+                let row_start = keys[13].parse().context("row start")?;
+                let row_span = keys[14].parse().context("row span")?;
+                let col_start = keys[10].parse().context("col start")?;
+                let col_span = keys[11].parse().context("col span")?;
+                let plate_size = size_at(12)?;
+                let start: u16 = keys[5].parse().context("spectrum start wavelength")?;
+                let stop: u16 = keys[6].parse().context("spectrum stop wavelength")?;
+                let step: u16 = keys[7].parse().context("spectrum wavelength step")?;
+                if step == 0 || stop < start {
+                    bail!(
+                        "Bad spectrum wavelength range: {}-{} by {}",
+                        start,
+                        stop,
+                        step
+                    );
+                }
+                let wavelengths = (start..=stop)
+                    .step_by(step as usize)
+                    .map(Wavelength::Absorbance)
+                    .collect();
 
                 Self {
                     plate_size,
@@ -163,6 +945,10 @@ impl PlateInfo {
                     col_span,
                     reads,
                     wavelengths,
+                    delay_us: None,
+                    integration_us: None,
+                    od_unit: od_unit(keys),
+                    scan_points: None,
                 }
             }
             _ => bail!(
@@ -171,19 +957,72 @@ impl PlateInfo {
                 read_mode
             ),
         };
+        if plate_size.is_some() {
+            info.check_fits()?;
+        }
 
         Ok(info)
     }
 
+    /// Fail if the read region runs off the plate
+    fn check_fits(&self) -> Result<()> {
+        let (rows, cols) = plate_dimensions(self.plate_size)?;
+        let last_row = self.row_start.max(1) as u32 + self.row_span as u32 - 1;
+        let last_col = self.col_start.max(1) as u32 + self.col_span as u32 - 1;
+        if last_row > rows as u32 || last_col > cols as u32 {
+            bail!(
+                "Read region (rows {}-{}, cols {}-{}) doesn't fit on a {} well plate of {} x {}",
+                self.row_start.max(1),
+                last_row,
+                self.col_start.max(1),
+                last_col,
+                self.plate_size,
+                rows,
+                cols
+            );
+        }
+        Ok(())
+    }
+
+    /// Values of a read, counting each point of a well scan
     fn total_wells_read(&self) -> usize {
-        self.row_span as usize * self.col_span as usize * self.wavelengths.len()
+        let points = self.scan_points.unwrap_or(1) as usize;
+        self.row_span as usize * self.col_span as usize * self.wavelengths.len() * points * points
+    }
+
+    /// Is the zero-indexed plate position within the rows / cols covered by the read?
+    fn in_read_region(&self, (r, c): WellRC) -> bool {
+        let row_start = self.row_start.saturating_sub(1);
+        let col_start = self.col_start.saturating_sub(1);
+
+        (row_start..row_start + self.row_span).contains(&r)
+            && (col_start..col_start + self.col_span).contains(&c)
     }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub(crate) enum ReadType {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ReadType {
     Endpoint,
     WellScan,
+    Kinetic,
+    /// an absorbance sweep, printed as one line per wavelength step rather than a grid.
+    /// Its settings keys (counting from 0 after the read mode) are 2 = reads, 5 / 6 / 7 =
+    /// start / stop / step wavelength, 10 / 11 = col start / span, 12 = plate size, and
+    /// 13 / 14 = row start / span
+    Spectrum,
+}
+
+impl ReadType {
+    /// Name as written in the export's settings row
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Endpoint => "Endpoint",
+            Self::WellScan => "Well Scan",
+            Self::Kinetic => "Kinetic",
+            Self::Spectrum => "Spectrum",
+        }
+    }
 }
 
 impl FromStr for ReadType {
@@ -193,15 +1032,32 @@ impl FromStr for ReadType {
         match s {
             "Well Scan" => Ok(Self::WellScan),
             "Endpoint" => Ok(Self::Endpoint),
+            "Kinetic" => Ok(Self::Kinetic),
+            "Spectrum" => Ok(Self::Spectrum),
             _ => Err(anyhow!("Unsupported M5 read type: {}", s)),
         }
     }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub(crate) enum ReadMode {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ReadMode {
     Fluorescence,
     Absorbance,
+    FluorescencePolarization,
+    TimeResolved,
+}
+
+impl ReadMode {
+    /// Name used in the output's Read Mode column
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fluorescence => "Fluorescence",
+            Self::Absorbance => "Absorbance",
+            Self::FluorescencePolarization => "Fluorescence Polarization",
+            Self::TimeResolved => "Time Resolved Fluorescence",
+        }
+    }
 }
 
 impl FromStr for ReadMode {
@@ -211,80 +1067,687 @@ impl FromStr for ReadMode {
         match s {
             "Fluorescence" => Ok(Self::Fluorescence),
             "Absorbance" => Ok(Self::Absorbance),
-            _ => Err(anyhow::anyhow!("Unsupported read mode: {}", s)),
+            "Fluorescence Polarization" | "Polarization" => Ok(Self::FluorescencePolarization),
+            "Time Resolved" | "Time Resolved Fluorescence" | "TRF" => Ok(Self::TimeResolved),
+            _ => Err(M5Error::UnsupportedReadMode(s.to_string()).into()),
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-pub(crate) struct ReadInfo {
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TempUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+impl TempUnit {
+    /// Convert a temperature in degrees Celsius to this unit
+    pub fn convert(self, c: R64) -> R64 {
+        match self {
+            Self::Celsius => c,
+            Self::Fahrenheit => c * 9.0 / 5.0 + 32.0,
+            Self::Kelvin => c + 273.15,
+        }
+    }
+
+    /// Convert a temperature in this unit to degrees Celsius
+    pub fn to_celsius(self, t: R64) -> R64 {
+        match self {
+            Self::Celsius => t,
+            Self::Fahrenheit => (t - 32.0) * 5.0 / 9.0,
+            Self::Kelvin => t - 273.15,
+        }
+    }
+}
+
+/// The degree symbol as it comes out of each wrong decoding of a MacRoman, Windows-1252, or
+/// UTF-8 export (`°` itself being the right one); invalid UTF-8 becomes the replacement
+/// character
+const DEGREE_VARIANTS: &[&str] = &["°", "Â°", "¬∞", "¡", "∞", "\u{FFFD}", ""];
+
+/// The temperature unit of a `Temperature(°C)` or `Temperature(°F)` column header, however
+/// the degree symbol was decoded
+pub(crate) fn is_temp_header(col: &str) -> Option<TempUnit> {
+    let unit = col.strip_prefix("Temperature(")?.strip_suffix(')')?;
+    let (degree, unit) = match unit.strip_suffix('C') {
+        Some(degree) => (degree, TempUnit::Celsius),
+        None => (unit.strip_suffix('F')?, TempUnit::Fahrenheit),
+    };
+
+    DEGREE_VARIANTS.contains(&degree).then_some(unit)
+}
+
+impl FromStr for TempUnit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "c" | "C" => Ok(Self::Celsius),
+            "f" | "F" => Ok(Self::Fahrenheit),
+            "k" | "K" => Ok(Self::Kelvin),
+            _ => Err(anyhow!("Unknown temperature unit: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for TempUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self {
+            Self::Celsius => "C",
+            Self::Fahrenheit => "F",
+            Self::Kelvin => "K",
+        };
+        f.write_str(unit)
+    }
+}
+
+/// Unit of absorbance values: optical density, or thousandths of it
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OdUnit {
+    #[default]
+    Od,
+    MilliOd,
+}
+
+impl OdUnit {
+    /// Convert a value in this unit to `to`
+    pub fn convert(self, value: f64, to: Self) -> f64 {
+        value * to.per_od() / self.per_od()
+    }
+
+    /// How many of this unit make up 1 OD
+    pub fn per_od(self) -> f64 {
+        match self {
+            Self::Od => 1.0,
+            Self::MilliOd => 1000.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Od => "OD",
+            Self::MilliOd => "mOD",
+        }
+    }
+}
+
+impl FromStr for OdUnit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "od" => Ok(Self::Od),
+            "mod" => Ok(Self::MilliOd),
+            _ => Err(anyhow!("Unknown absorbance unit: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for OdUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReadInfo {
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::r64_serde"))]
     pub temp: R64,
     pub unique: UniqueReadInfo,
+    /// cells of the read region that were empty, and so left out of the read's wells;
+    /// always 0 with `emit_empty`
+    pub empty_cells: usize,
 }
 
 impl ReadInfo {
-    fn parse_cols(c1: &str, c2: &str, rtype: ReadType) -> Result<Self> {
-        let unique = match rtype {
-            ReadType::Endpoint => UniqueReadInfo::None,
-            ReadType::WellScan => {
-                let time = parse_time(c1).context("parsing time column")?;
-                UniqueReadInfo::Time(time)
+    fn parse_cols(
+        c1: &str,
+        c2: &str,
+        settings: &PlateSettings,
+        opts: &ParseOptions,
+    ) -> Result<Self> {
+        let unique = match settings.read_type {
+            ReadType::Endpoint | ReadType::Spectrum => UniqueReadInfo::None,
+            ReadType::WellScan | ReadType::Kinetic => {
+                let time = parse_time(&opts.number(c1)).context("parsing time column")?;
+                UniqueReadInfo::Time(time, c1.trim().to_string())
             }
         };
 
-        let temp = c2.parse().map(r64).context("parsing temperature value")?;
+        // some endpoint exports put the temperature in the first column, without a time
+        let temp_col = match settings.read_type {
+            ReadType::Endpoint if c2.trim().is_empty() => c1,
+            _ => c2,
+        };
+        let temp = opts
+            .number(temp_col)
+            .parse()
+            .map(|t| settings.temp_unit.to_celsius(r64(t)))
+            .context("parsing temperature value")?;
 
-        Ok(Self { temp, unique })
+        Ok(Self {
+            temp,
+            unique,
+            empty_cells: 0,
+        })
     }
 
-    pub(crate) fn get_time(&self) -> Option<R64> {
+    pub fn get_time(&self) -> Option<R64> {
         match self.unique {
             UniqueReadInfo::None => None,
-            UniqueReadInfo::Time(time) => Some(time),
+            UniqueReadInfo::Time(time, _) => Some(time),
+        }
+    }
+
+    /// The time as written in the export, e.g. `01:30:00`
+    pub fn raw_time(&self) -> Option<&str> {
+        match &self.unique {
+            UniqueReadInfo::None => None,
+            UniqueReadInfo::Time(_, raw) => Some(raw),
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-pub(crate) enum UniqueReadInfo {
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum UniqueReadInfo {
     None,
-    Time(R64),
+    /// decimal hours, and the time as written in the export
+    Time(
+        #[cfg_attr(feature = "serde", serde(with = "crate::utils::r64_serde"))] R64,
+        String,
+    ),
 }
 
 pub type WellRC = (u8, u8);
-#[derive(Debug)]
-pub(crate) struct WellValue {
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WellValue {
     pub wavelength: Wavelength,
     /// zero-indexed (row, col)
     pub well: WellRC,
-    pub value: f64,
+    pub value: CellValue,
+    /// `value` relative to a reference, filled in by output normalization; never set by
+    /// parsing
+    pub normalized: Option<f64>,
+    /// `value` corrected to a 1 cm pathlength, filled in by output pathlength correction;
+    /// never set by parsing
+    pub corrected: Option<f64>,
+    /// zero-indexed (row, col) of the point in the well, for a well scan read
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub point: Option<WellRC>,
+    /// the value as written in the export, kept with [`ParseOptions::raw_values`]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub raw: Option<String>,
+}
+
+/// Well readings that mean the detector saturated, rather than a number
+pub const OVERFLOW_SENTINELS: &[&str] = &["Overflw", "Overflow", "OVRFLW", "###"];
+
+/// Background and sample reference wavelengths [nm] of a pathlength check read; either of
+/// the two sample wavelengths can be used
+pub const PATHCHECK_NM: [u16; 3] = [900, 977, 1000];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CellValue {
+    Num(f64),
+    /// saturated reading, one of [`OVERFLOW_SENTINELS`]
+    Overflow,
+    /// empty / unread cell
+    Masked,
+    /// a number that isn't finite, e.g. `NaN` or `inf` from a failed reading. Kept apart
+    /// from `Num` so it can't end up in any calculation
+    NonFinite(f64),
+}
+
+impl CellValue {
+    pub fn num(self) -> Option<f64> {
+        match self {
+            Self::Num(v) => Some(v),
+            Self::Overflow | Self::Masked | Self::NonFinite(_) => None,
+        }
+    }
+
+    /// Empty for numeric values, otherwise a short description for the output status column
+    pub fn status(self) -> &'static str {
+        match self {
+            Self::Num(_) => "",
+            Self::Overflow => "Overflow",
+            Self::Masked => "Masked",
+            Self::NonFinite(_) => "Non-finite",
+        }
+    }
+}
+
+impl FromStr for CellValue {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if OVERFLOW_SENTINELS.contains(&s) {
+            Ok(Self::Overflow)
+        } else {
+            let v: f64 = s.parse()?;
+            Ok(if v.is_finite() {
+                Self::Num(v)
+            } else {
+                Self::NonFinite(v)
+            })
+        }
+    }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-pub(crate) enum Wavelength {
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Wavelength {
     Fluorescence(u16, u16), // ex, em
     Absorbance(u16),
+    /// reference wavelength of an M5e PathCheck read, one of [`PATHCHECK_NM`]
+    PathCheck(u16),
+    /// one channel of a fluorescence polarization read
+    Polarization {
+        ex: u16,
+        em: u16,
+        channel: PolarizationChannel,
+    },
 }
 
-fn get_block_count(s: &str) -> Result<u16> {
-    let mut it = s.split_whitespace().take(2);
+/// The output's wavelength description, e.g. `600nm` or `ex 485nm / em 535nm`
+impl fmt::Display for Wavelength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Absorbance(abs) => write!(f, "{}nm", abs),
+            Self::PathCheck(nm) => write!(f, "{}nm PathCheck", nm),
+            Self::Fluorescence(ex, em) => write!(f, "ex {}nm / em {}nm", ex, em),
+            Self::Polarization { ex, em, channel } => {
+                write!(f, "ex {}nm / em {}nm {}", ex, em, channel)
+            }
+        }
+    }
+}
+
+/// Parses `ex/em` (e.g. `485/535`) as fluorescence, `ex/em/p` or `ex/em/s` as the parallel
+/// or perpendicular polarization channel, a single value (`600`) as absorbance, or one with
+/// a `pc` suffix (`977pc`) as a PathCheck reference
+impl FromStr for Wavelength {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split('/').map(str::trim);
+
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(abs), None, None) => match abs.strip_suffix("pc") {
+                Some(nm) => nm.parse().map(Self::PathCheck).map_err(Into::into),
+                None => abs.parse().map(Self::Absorbance).map_err(Into::into),
+            },
+            (Some(ex), Some(em), None) => {
+                rmap2(ex.parse(), em.parse(), Self::Fluorescence).map_err(Into::into)
+            }
+            (Some(ex), Some(em), Some(channel)) if parts.next().is_none() => {
+                Ok(Self::Polarization {
+                    ex: ex.parse()?,
+                    em: em.parse()?,
+                    channel: channel.parse()?,
+                })
+            }
+            _ => Err(anyhow!("too many parts")),
+        }
+        .with_context(|| anyhow!("Bad wavelength: {}", s))
+    }
+}
+
+/// Emission channel of a fluorescence polarization read, relative to the excitation
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PolarizationChannel {
+    /// `p` in wavelength specs
+    Parallel,
+    /// `s` in wavelength specs
+    Perpendicular,
+    /// `mp` in wavelength specs: not read, but computed from both channels by the output's
+    /// `fp_mp` option
+    Millipolarization,
+}
+
+impl PolarizationChannel {
+    /// The channels read, in the order their grids are striped in the export
+    pub const ALL: [Self; 2] = [Self::Parallel, Self::Perpendicular];
+}
+
+impl FromStr for PolarizationChannel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "p" | "parallel" => Ok(Self::Parallel),
+            "s" | "perpendicular" => Ok(Self::Perpendicular),
+            "mp" => Ok(Self::Millipolarization),
+            _ => Err(anyhow!("Unknown polarization channel: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for PolarizationChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Parallel => "parallel",
+            Self::Perpendicular => "perpendicular",
+            Self::Millipolarization => "mP",
+        })
+    }
+}
+
+/// Below this many blocks, splitting up the file costs more than parsing in parallel saves.
+/// Splitting adds roughly 10-30% over the serial parse, so only a few threads make it
+/// worthwhile
+#[cfg(feature = "rayon")]
+const PARALLEL_BLOCK_THRESHOLD: u16 = 16;
+
+/// Split the (already decoded) text into blocks at their `~End` lines, then parse the
+/// blocks in parallel. `line` is how many lines of the input were read before the blocks
+#[cfg(feature = "rayon")]
+fn parse_blocks_par<R: BufRead>(
+    mut rdr: R,
+    line: usize,
+    experiment: usize,
+    block_count: u16,
+    opts: &ParseOptions,
+) -> Result<Vec<Block>> {
+    use rayon::prelude::*;
+
+    // (lines before the chunk, chunk)
+    let mut chunks = Vec::with_capacity(block_count as usize);
+    let mut chunk = String::new();
+    let (mut line, mut chunk_start) = (line, line);
+    while chunks.len() < block_count as usize {
+        let start = chunk.len();
+        if read_line(&mut rdr, &mut chunk).context("reading block")? == 0 {
+            // leave a truncated last block for the parser to report
+            if !chunk.is_empty() {
+                chunks.push((chunk_start, chunk));
+            }
+            break;
+        }
+        line += 1;
+        if block_end(&chunk[start..]).is_some() {
+            chunks.push((chunk_start, std::mem::take(&mut chunk)));
+            chunk_start = line;
+        }
+    }
+
+    let truncated = !chunk_ended(chunks.last().map(|(_, c)| c));
+    let results = chunks
+        .into_par_iter()
+        .enumerate()
+        .map(|(i, (start, chunk))| {
+            let mut rdr = LineCounter::new(chunk.as_bytes());
+            let pos = BlockPos {
+                experiment,
+                block: i + 1,
+            };
+            Block::from_rdr(&mut rdr, &mut String::new(), pos, opts)
+                .map_err(|e| Error::from(M5Error::from_anyhow(e, start + rdr.line_count())))
+                .with_context(|| anyhow!("parsing {}", pos))
+        })
+        .collect::<Vec<_>>();
+
+    let total = results.len();
+    let mut blocks = Vec::with_capacity(total);
+    for (i, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(block) => blocks.push(block),
+            Err(e) if opts.lenient && truncated && i + 1 == total => {
+                warn_truncated(opts, blocks.len(), &e, None);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if blocks.len() < block_count as usize {
+        if !opts.lenient {
+            bail!(
+                "Expected {} blocks, but file ended after {}",
+                block_count,
+                blocks.len()
+            );
+        } else if !truncated {
+            let e = anyhow!("expected {} blocks", block_count);
+            warn_truncated(opts, blocks.len(), &e, None);
+        }
+    }
+
+    Ok(blocks)
+}
+
+#[cfg(feature = "rayon")]
+fn chunk_ended(chunk: Option<&String>) -> bool {
+    !matches!(chunk, Some(c) if c.trim_end().lines().last().and_then(block_end).is_none())
+}
+
+/// Parse the blocks of export `experiment`, in parallel if there are enough of them
+fn parse_export<R: BufRead>(
+    rdr: &mut LineCounter<R>,
+    buf: &mut String,
+    experiment: usize,
+    block_count: u16,
+    opts: &ParseOptions,
+) -> Result<Vec<Block>> {
+    #[cfg(feature = "rayon")]
+    {
+        if block_count >= PARALLEL_BLOCK_THRESHOLD && rayon::current_num_threads() > 1 {
+            let line = rdr.line_count();
+            return parse_blocks_par(rdr, line, experiment, block_count, opts);
+        }
+    }
+
+    parse_blocks(rdr, buf, experiment, block_count, opts, |rdr, buf, pos| {
+        Block::from_rdr(rdr, buf, pos, opts)
+    })
+}
+
+/// Where a block is in the input
+#[derive(Debug, Copy, Clone)]
+struct BlockPos {
+    /// 1-based index of the block's export, in concatenated exports
+    experiment: usize,
+    /// 1-based position of the block in its export
+    block: usize,
+}
+
+impl fmt::Display for BlockPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.experiment {
+            1 => write!(f, "block {}", self.block),
+            experiment => write!(f, "block {} of export {}", self.block, experiment),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn first_experiment() -> usize {
+    1
+}
+
+/// Parse each of the blocks of export `experiment` with `parse`, which also gets the
+/// block's position. A lenient parse stops early, keeping the
+/// blocks so far, when a block fails because the input ended
+fn parse_blocks<R, F>(
+    rdr: &mut LineCounter<R>,
+    buf: &mut String,
+    experiment: usize,
+    block_count: u16,
+    opts: &ParseOptions,
+    mut parse: F,
+) -> Result<Vec<Block>>
+where
+    R: BufRead,
+    F: FnMut(&mut dyn BufRead, &mut String, BlockPos) -> Result<Block>,
+{
+    let mut blocks = Vec::with_capacity(block_count as usize);
+
+    for i in 0..block_count as usize {
+        let pos = BlockPos {
+            experiment,
+            block: i + 1,
+        };
+        match parse(rdr, buf, pos).with_context(|| anyhow!("parsing {}", pos)) {
+            Ok(block) => blocks.push(block),
+            Err(e) if opts.lenient && matches!(rdr.fill_buf(), Ok(rest) if rest.is_empty()) => {
+                let e = M5Error::from_anyhow(e, rdr.line_count());
+                warn_truncated(opts, blocks.len(), &e.into(), Some(rdr.line_count()));
+                break;
+            }
+            Err(e) => return Err(e),
+        }
+        buf.clear();
+    }
+
+    Ok(blocks)
+}
+
+/// `line` is the last line read, if known
+fn warn_truncated(opts: &ParseOptions, kept: usize, e: &Error, line: Option<usize>) {
+    opts.warnings.warn(Warning::Truncated {
+        kept,
+        reason: format!("{:#}", e),
+        line,
+    });
+}
+
+/// [`read_line`] where running out of input is an error
+fn read_data_line(rdr: &mut dyn BufRead, buf: &mut String) -> Result<()> {
+    if read_line(rdr, buf)? == 0 {
+        // the line is filled in from the reader once the error reaches the top
+        bail!(M5Error::UnexpectedEof { line: 0 });
+    }
+    Ok(())
+}
+
+/// Read a line into `buf` like [`BufRead::read_line`], but with a Windows `\r\n` ending
+/// normalized to `\n`, so carriage returns never end up in the last field of a row
+fn read_line<R: BufRead + ?Sized>(rdr: &mut R, buf: &mut String) -> io::Result<usize> {
+    let read = rdr.read_line(buf)?;
+    if buf.ends_with("\r\n") {
+        buf.truncate(buf.len() - 2);
+        buf.push('\n');
+    } else if buf.ends_with('\r') {
+        buf.pop();
+    }
+
+    Ok(read)
+}
+
+/// How many lines of preamble can come before the `##BLOCKS=` magic
+const MAX_PREAMBLE_LINES: usize = 64;
+
+/// Read the block count, along with the (non-blank) preamble lines before it
+fn read_block_count<R: BufRead>(rdr: &mut R, buf: &mut String) -> Result<(u16, Vec<String>)> {
+    let mut metadata = Vec::new();
+    for _ in 0..=MAX_PREAMBLE_LINES {
+        buf.clear();
+        if read_line(rdr, buf).context("reading block count")? == 0 {
+            break;
+        }
+        if let Some(line) = blocks_magic(buf) {
+            let block_count = get_block_count(line).context("parsing initial blocks count")?;
+            buf.clear();
+            return Ok((block_count, metadata));
+        }
+        let line = buf.trim_start_matches('\u{feff}').trim();
+        if !line.is_empty() {
+            metadata.push(line.to_string());
+        }
+    }
+
+    let lines = metadata.iter().map(String::as_str);
+    Err(missing_magic(lines)).context("reading block count")
+}
+
+/// Read an input up to and including its `##BLOCKS=` line, to check that it's an export
+/// before converting it. Returns the text read, which is then parsed as the start of the
+/// input
+pub fn read_to_blocks_magic<R: BufRead>(rdr: &mut R) -> Result<String> {
+    let mut text = String::new();
+    for _ in 0..=MAX_PREAMBLE_LINES {
+        let start = text.len();
+        if rdr.read_line(&mut text).context("reading block count")? == 0 {
+            break;
+        }
+        if let Some(line) = blocks_magic(&text[start..]) {
+            get_block_count(line).context("parsing initial blocks count")?;
+            return Ok(text);
+        }
+    }
+
+    Err(missing_magic(text.lines())).context("reading block count")
+}
+
+/// `line`, less any BOM left in the text (as in input decoded elsewhere, or an export
+/// concatenated after another), if it's a `##BLOCKS=` magic line
+fn blocks_magic(line: &str) -> Option<&str> {
+    let line = line.trim_start_matches('\u{feff}');
+    line.trim_start().starts_with("##BLOCKS=").then_some(line)
+}
 
-    match (it.next(), it.next()) {
-        (Some("##BLOCKS="), Some(b)) => Ok(b),
-        _ => Err(anyhow!("Missing BLOCKS magic string")),
+/// The error for an input without the magic, holding the first of its `lines` with any text
+fn missing_magic<'a>(lines: impl Iterator<Item = &'a str>) -> M5Error {
+    let first_line = lines
+        .map(|l| l.trim_start_matches('\u{feff}').trim())
+        .find(|l| !l.is_empty())
+        .map(|l| l.chars().take(80).collect())
+        .unwrap_or_default();
+    M5Error::MissingBlocksMagic { first_line }
+}
+
+/// The block count of a further export concatenated after the last block, along with its
+/// preamble lines. `None` if the input ends first, or only has text without a `##BLOCKS=`
+/// magic left, which is ignored
+fn read_next_block_count<R: BufRead>(
+    rdr: &mut R,
+    buf: &mut String,
+) -> Result<Option<(u16, Vec<String>)>> {
+    match read_block_count(rdr, buf) {
+        Ok(next) => Ok(Some(next)),
+        Err(e) if matches!(e.downcast_ref(), Some(M5Error::MissingBlocksMagic { .. })) => {
+            buf.clear();
+            Ok(None)
+        }
+        Err(e) => Err(e),
     }
-    .and_then(|b| b.parse().map_err(Into::into))
+}
+
+/// The count can follow the magic after a space or tab (`##BLOCKS= 3`), or be glued to it
+/// (`##BLOCKS=3`)
+fn get_block_count(s: &str) -> Result<u16> {
+    let count =
+        s.trim_start()
+            .strip_prefix("##BLOCKS=")
+            .ok_or_else(|| M5Error::MissingBlocksMagic {
+                first_line: s.trim().to_string(),
+            })?;
+
+    count
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .parse()
+        .map_err(Into::into)
 }
 
 fn parse_time(s: &str) -> Result<R64> {
     let mut it = s.splitn(3, ':');
     let h: f64 = it
         .next()
-        .ok_or_else(|| anyhow!("No hours in time: {}, s"))
+        .ok_or_else(|| anyhow!("No hours in time: {}", s))
         .and_then(|h| h.parse().map_err(Into::into))?;
     let m: f64 = it
         .next()
-        .ok_or_else(|| anyhow!("No minutes in time: {}, s"))
+        .ok_or_else(|| anyhow!("No minutes in time: {}", s))
         .and_then(|m| m.parse().map_err(Into::into))?;
     let s: f64 = it
         .next()
@@ -294,85 +1757,532 @@ fn parse_time(s: &str) -> Result<R64> {
     Ok(r64(h + (m / 60.0) + (s / (60.0 * 60.0))))
 }
 
+const PLATE_SIZES: &[(u32, (u8, u8))] = &[
+    (6, (2, 3)),
+    (12, (3, 4)),
+    (24, (4, 6)),
+    (48, (6, 8)),
+    (96, (8, 12)),
+    (384, (16, 24)),
+    (1536, (32, 48)),
+];
+
+/// (rows, cols) of a plate with `size` wells
+pub(crate) fn plate_dimensions(size: u32) -> Result<(u8, u8)> {
+    PLATE_SIZES
+        .iter()
+        .find(|(s, _)| *s == size)
+        .map(|&(_, dims)| dims)
+        .ok_or_else(|| M5Error::UnsupportedPlateSize(size).into())
+}
+
+/// Comma-separated list of the plate sizes [`plate_dimensions`] knows
+pub(crate) fn supported_plate_sizes() -> String {
+    PLATE_SIZES
+        .iter()
+        .map(|(s, _)| s.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The plate rows and columns printed in a read's grid.
+///
+/// Grids usually cover the whole plate, with empty cells outside the read region. A partial
+/// read may instead be printed as just its read region, which shows in the column header
+/// row: it then numbers `col_span` columns rather than every plate column.
+///
+/// A read of several wavelengths usually prints each wavelength's columns in turn, with a
+/// spacer column between them (header `1 2 3 … 12 _ 1 2 3 …`). Some exports interleave them
+/// instead, one value per wavelength for each column (header `1 1 2 2 3 3 …`), which shows
+/// as a repeated first column number
+#[derive(Debug, Clone, Copy)]
+struct Grid {
+    rows: u8,
+    cols: u8,
+    /// plate position of the grid's first cell
+    origin: WellRC,
+    /// wavelengths are interleaved column by column rather than in blocks of columns
+    interleaved: bool,
+    /// each printed line is a plate column, so `rows` and `cols` (along with `origin`) are
+    /// plate columns and rows
+    transposed: bool,
+    /// points per side of the square grid read in each well of a well scan
+    points: Option<u8>,
+}
+
+impl Grid {
+    /// From the temperature and column header row
+    fn from_header(
+        header: &str,
+        settings: &PlateSettings,
+        orientation: Orientation,
+    ) -> Result<Self> {
+        let info = &settings.info;
+        let (total_rows, total_cols) = plate_dimensions(info.plate_size)?;
+        let transposed = orientation == Orientation::ColMajor;
+        // (lines, values per line) of the printed grid and the read region
+        let swap = |a, b| if transposed { (b, a) } else { (a, b) };
+        let (total_rows, total_cols) = swap(total_rows, total_cols);
+        let (row_span, col_span) = swap(info.row_span, info.col_span);
+        let (row_start, col_start) = swap(info.row_start, info.col_start);
+        // the first wavelength's column numbers, up to its spacer column, or every
+        // wavelength's if they're interleaved
+        let labels = header
+            .split('\t')
+            .skip(2)
+            .map(str::trim)
+            .take_while(|s| !s.is_empty())
+            .collect::<Vec<_>>();
+        let waves = info.wavelengths.len();
+        let interleaved = waves > 1 && labels.len() > 1 && labels[0] == labels[1];
+        let header_cols = if interleaved {
+            labels.len() / waves
+        } else {
+            labels.len()
+        };
+        // a well scan of n x n points per well prints each well as an n x n block of
+        // values: n lines per plate row and n values per plate column, each value with a
+        // column number of its own. Only grids of the whole plate's columns are recognised
+        let points = match settings.read_type {
+            ReadType::WellScan => match header_cols / total_cols as usize {
+                n if n > 1 && header_cols % total_cols as usize == 0 => Some(n as u8),
+                _ => Some(1),
+            },
+            _ => None,
+        };
+
+        let grid = if col_span != total_cols && header_cols == col_span as usize {
+            Self {
+                rows: row_span,
+                cols: col_span,
+                origin: (row_start.saturating_sub(1), col_start.saturating_sub(1)),
+                interleaved,
+                transposed,
+                points,
+            }
+        } else {
+            Self {
+                rows: total_rows,
+                cols: total_cols,
+                origin: (0, 0),
+                interleaved,
+                transposed,
+                points,
+            }
+        };
+
+        Ok(grid)
+    }
+
+    /// Plate well, and point in the well for a well scan, of value `c` of the grid's
+    /// printed line `r`, given the plate position of the grid's first cell.
+    ///
+    /// With n x n points per well, line `r` holds point row `r % n` of the wells of grid
+    /// row `r / n`, and value `c` is point column `c % n` of the well in grid column
+    /// `c / n`. In a transposed grid both the wells and their points are swapped
+    fn locate(&self, origin: WellRC, r: usize, c: usize) -> (WellRC, Option<WellRC>) {
+        let n = self.points.unwrap_or(1) as usize;
+        let line = (origin.0 + (r / n) as u8, (r % n) as u8);
+        let value = (origin.1 + (c / n) as u8, (c % n) as u8);
+        let (well, point) = match self.transposed {
+            true => ((value.0, line.0), (value.1, line.1)),
+            false => ((line.0, value.0), (line.1, value.1)),
+        };
+        (well, self.points.map(|_| point))
+    }
+}
+
 fn parse_plate(
     rdr: &mut dyn BufRead,
     buf: &mut String,
     settings: &PlateSettings,
+    grid: Grid,
+    opts: &ParseOptions,
+) -> Result<(ReadInfo, Vec<WellValue>)> {
+    let total_wells = settings.info.total_wells_read();
+    let mut output = Vec::with_capacity(total_wells);
+    // printed lines per grid row and values per grid column
+    let points = grid.points.unwrap_or(1) as usize;
+    let total_cols = grid.cols as usize * points;
+
+    let mut read_info = None;
+    let mut time_col = String::new();
+
+    // a read of only some plate rows may print just those rows rather than the whole
+    // plate, which shows as the spacer line coming right after its `row_span` rows
+    let (row_span, row_start) = match grid.transposed {
+        true => (settings.info.col_span, settings.info.col_start),
+        false => (settings.info.row_span, settings.info.row_start),
+    };
+    let mut origin = grid.origin;
+    let mut spacer_read = false;
+    buf.clear();
+    for r in 0..grid.rows as usize * points {
+        let start = buf.len();
+        read_data_line(rdr, buf)?;
+        if r == row_span as usize * points && buf[start..].trim_end_matches('\n').is_empty() {
+            buf.truncate(start);
+            origin.0 = row_start.saturating_sub(1);
+            spacer_read = true;
+            break;
+        }
+    }
+
+    for (r, line_text) in buf.lines().enumerate() {
+        let mut line = line_text.split('\t');
+
+        let c1 = line
+            .next()
+            .ok_or_else(|| anyhow!("expected info col 1: {}", line_text))?;
+        let c2 = line
+            .next()
+            .ok_or_else(|| anyhow!("expected info col 2: {}", line_text))?;
+        // the grid's first row holds its time and temperature, with the rest left blank;
+        // fall back to a later row in case the first one is missing the temperature. An
+        // endpoint read has no time, so its temperature may be in the first column instead
+        if read_info.is_none() {
+            if time_col.is_empty() {
+                time_col.push_str(c1.trim());
+            }
+            let endpoint_temp = settings.read_type == ReadType::Endpoint && !c1.trim().is_empty();
+            if !c2.trim().is_empty() || endpoint_temp {
+                read_info = Some(ReadInfo::parse_cols(&time_col, c2, settings, opts)?);
+            }
+        }
+
+        let locate = |(c, s)| {
+            let (well, point) = grid.locate(origin, r, c);
+            (well, point, s)
+        };
+        let waves = settings.info.wavelengths.len();
+        for (i, &wavelength) in settings.info.wavelengths.iter().enumerate() {
+            let info = &settings.info;
+            if grid.interleaved {
+                // every `waves`th value, starting from this wavelength's
+                let values = line.clone().skip(i).step_by(waves).take(total_cols);
+                let values = values.enumerate().map(locate);
+                parse_row_values(values, wavelength, info, opts, &mut output)?;
+            } else {
+                // each wavelength's plate row is `total_cols` values followed by a spacer
+                // column
+                let values = line.by_ref().take(total_cols).enumerate().map(locate);
+                parse_row_values(values, wavelength, info, opts, &mut output)?;
+                let _spacer = line.next();
+            }
+        }
+    }
+
+    buf.clear();
+    if !spacer_read {
+        read_line(rdr, buf)?;
+        // TODO: check for spacer row
+    }
+
+    let read_info = read_info.ok_or_else(|| anyhow!("no row of the read has a temperature"))?;
+
+    Ok((read_info, output))
+}
+
+/// Warn about any overflowed or non-finite values of read `read` (1-based), or fail on the
+/// first non-finite one with `--strict-values`
+fn check_values(
+    settings: &PlateSettings,
+    read: usize,
+    wells: &[WellValue],
+    opts: &ParseOptions,
+) -> Result<()> {
+    let mut overflow = wells.iter().filter(|w| w.value == CellValue::Overflow);
+    if let Some(first) = overflow.next() {
+        opts.warnings.warn(Warning::Overflow {
+            plate: settings.name.clone(),
+            read,
+            well: well_name(first.well),
+            wavelength: first.wavelength.to_string(),
+            count: overflow.count() + 1,
+        });
+    }
+
+    let mut non_finite = wells
+        .iter()
+        .filter(|w| matches!(w.value, CellValue::NonFinite(_)));
+    let first = match non_finite.next() {
+        Some(well) => well,
+        None => return Ok(()),
+    };
+
+    let name = well_name(first.well);
+    if opts.strict_values {
+        bail!(M5Error::NonFiniteValue {
+            well: name,
+            wavelength: first.wavelength.to_string(),
+            line: 0,
+        });
+    }
+    opts.warnings.warn(Warning::NonFinite {
+        plate: settings.name.clone(),
+        read,
+        well: name,
+        wavelength: first.wavelength.to_string(),
+        count: non_finite.count() + 1,
+    });
+
+    Ok(())
+}
+
+/// Warn about any well listed twice for the same wavelength in read `read` (1-based), or
+/// fail with `--strict-geometry`
+fn check_duplicates(
+    settings: &PlateSettings,
+    read: usize,
+    wells: &[WellValue],
+    opts: &ParseOptions,
+) -> Result<()> {
+    let mut seen = HashSet::with_capacity(wells.len());
+
+    for well in wells {
+        if seen.insert((well.well, well.point, well.wavelength)) {
+            continue;
+        }
+        let name = well_name(well.well);
+        if opts.strict_geometry {
+            bail!(M5Error::DuplicateWell {
+                well: name,
+                wavelength: well.wavelength.to_string(),
+                line: 0,
+            });
+        }
+        opts.warnings.warn(Warning::DuplicateWell {
+            plate: settings.name.clone(),
+            read,
+            well: name,
+            wavelength: well.wavelength.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// With `--strict-geometry`, make sure a read (parsed with masked cells kept) has a value
+/// for every well of the read region and wavelength, then drop the masked cells again
+/// unless they were asked for
+fn check_geometry(
+    settings: &PlateSettings,
+    opts: &ParseOptions,
+    mut wells: Vec<WellValue>,
+) -> Result<Vec<WellValue>> {
+    if !opts.strict_geometry {
+        return Ok(wells);
+    }
+
+    let expected = settings.info.total_wells_read();
+    if wells.len() != expected {
+        bail!(M5Error::WellCountMismatch {
+            expected,
+            found: wells.len(),
+        });
+    }
+    if !opts.emit_empty {
+        wells.retain(|w| !matches!(w.value, CellValue::Masked));
+    }
+
+    Ok(wells)
+}
+
+/// Spectrum reads have one line per wavelength step (wavelength, temperature, then one
+/// column per well of the read region in row-major order) rather than a plate grid. Any
+/// fields after the read region's wells are ignored
+fn parse_spectrum(
+    rdr: &mut dyn BufRead,
+    buf: &mut String,
+    settings: &PlateSettings,
+    opts: &ParseOptions,
 ) -> Result<(ReadInfo, Vec<WellValue>)> {
     let total_wells = settings.info.total_wells_read();
     let mut output = Vec::with_capacity(total_wells);
-    let (total_rows, total_cols) = match settings.info.plate_size {
-        384 => Ok((16, 24)),
-        96 => Ok((8, 12)),
-        _ => Err(anyhow!(
-            "Unsupported plate size {}",
-            settings.info.plate_size
-        )),
-    }?;
+    let (row_span, col_span) = (settings.info.row_span, settings.info.col_span);
+    if row_span == 0 || col_span == 0 {
+        bail!(
+            "Spectrum read region has no wells ({} rows x {} cols)",
+            row_span,
+            col_span
+        );
+    }
+    let row_start = settings.info.row_start.saturating_sub(1);
+    let col_start = settings.info.col_start.saturating_sub(1);
+    // the plate well of the `i`th value, if the read region fits on a plate at all
+    let well_at = |i: usize| {
+        let (r, c) = (i / col_span as usize, i % col_span as usize);
+        let r = u8::try_from(r).ok().and_then(|r| row_start.checked_add(r));
+        let c = u8::try_from(c).ok().and_then(|c| col_start.checked_add(c));
+        r.zip(c)
+            .ok_or_else(|| anyhow!("Spectrum read region runs past row or column 255"))
+    };
 
     let mut read_info = None;
 
-    for r in 0..total_rows {
+    for &wavelength in &settings.info.wavelengths {
         buf.clear();
-        rdr.read_line(buf)?;
+        read_data_line(rdr, buf)?;
 
         let mut line = buf.split('\t');
 
         let c1 = line
             .next()
-            .ok_or_else(|| anyhow!("expected info col 1: {}", buf))?;
+            .ok_or_else(|| anyhow!("expected wavelength col: {}", buf))?;
         let c2 = line
             .next()
             .ok_or_else(|| anyhow!("expected info col 2: {}", buf))?;
         if read_info.is_none() {
-            read_info = Some(ReadInfo::parse_cols(c1, c2, settings.read_type)?);
+            read_info = Some(ReadInfo::parse_cols(c1, c2, settings, opts)?);
         }
 
-        // todo: just collect first...?
-        let row_values: Vec<_> = line.collect();
-
-        let values = row_values
-            .chunks(total_cols + 1)
-            .zip(settings.info.wavelengths.iter().copied())
-            .flat_map(|(values, wavelength)| {
-                let (values, _spacer) = values.split_at(total_cols);
-                parse_row_values(values, r, wavelength)
+        let values = line
+            .map(str::trim)
+            .take(row_span as usize * col_span as usize)
+            .enumerate()
+            .filter(|(_, s)| opts.emit_empty || !s.is_empty())
+            .map(|(i, s)| {
+                let well = well_at(i)?;
+                parse_cell(s, opts).map(|value| WellValue {
+                    wavelength,
+                    value,
+                    well,
+                    normalized: None,
+                    corrected: None,
+                    point: None,
+                    raw: opts.raw_values.then(|| s.to_string()),
+                })
             });
 
         for val in values {
-            let val = val.context("issue parsing well")?;
+            let val = val.with_context(|| anyhow!("issue parsing well at {}", c1))?;
             output.push(val);
         }
     }
 
     buf.clear();
-    rdr.read_line(buf)?;
-    // TODO: check for spacer row
+    read_line(rdr, buf)?;
 
     let read_info = read_info.ok_or_else(|| anyhow!("never found read info"))?;
 
     Ok((read_info, output))
 }
 
-fn parse_row_values<'a>(
-    values: &'a [&str],
-    r: u8,
+/// Parse one wavelength's values of a grid line, adding them to `output`. Each value comes
+/// with its plate well, and its point in the well for a well scan
+fn parse_row_values<'s, I>(
+    values: I,
     wavelength: Wavelength,
-) -> impl Iterator<Item = Result<WellValue>> + 'a {
-    values
-        .iter()
-        .copied()
-        .map(str::trim)
-        .enumerate()
-        .filter(|(_, s)| !s.is_empty())
-        .map(move |(c, value)| {
-            value
-                .parse()
-                .context("parsing well value")
-                .map(|value| WellValue {
-                    wavelength,
-                    value,
-                    well: (r, c as u8),
-                })
+    info: &PlateInfo,
+    opts: &ParseOptions,
+    output: &mut Vec<WellValue>,
+) -> Result<()>
+where
+    I: Iterator<Item = (WellRC, Option<WellRC>, &'s str)>,
+{
+    let values = values
+        .map(|(well, point, s)| (well, point, s.trim()))
+        .filter(|&(well, _, s)| !s.is_empty() || (opts.emit_empty && info.in_read_region(well)));
+
+    for (well, point, s) in values {
+        let value = parse_cell(s, opts).context("issue parsing well")?;
+        output.push(WellValue {
+            wavelength,
+            value,
+            well,
+            normalized: None,
+            corrected: None,
+            point,
+            raw: opts.raw_values.then(|| s.to_string()),
+        });
+    }
+
+    Ok(())
+}
+
+/// Empty cells are masked wells, rather than a parse failure
+fn parse_cell(s: &str, opts: &ParseOptions) -> Result<CellValue> {
+    if s.is_empty() {
+        Ok(CellValue::Masked)
+    } else {
+        opts.number(s).parse().map_err(|_| {
+            let cell = s.to_string();
+            M5Error::WellValueParse { line: 0, cell }.into()
         })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{MACINTOSH, UTF_8, WINDOWS_1252};
+
+    /// `Temperature(°C)` as written in MacRoman, UTF-8, and Latin-1, then decoded with each of
+    /// them, right or wrong
+    #[test]
+    fn temp_header_decodings() {
+        for degree in [&[0xA1][..], &[0xC2, 0xB0], &[0xB0]] {
+            for unit in ["C", "F"] {
+                let header = [b"Temperature(", degree, unit.as_bytes(), b")"].concat();
+                for encoding in [MACINTOSH, UTF_8, WINDOWS_1252] {
+                    let (col, _, _) = encoding.decode(&header);
+                    let expected = match unit {
+                        "C" => TempUnit::Celsius,
+                        _ => TempUnit::Fahrenheit,
+                    };
+                    assert_eq!(is_temp_header(&col), Some(expected), "{}", col);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn temp_header_unknown_degree_sign() {
+        assert_eq!(is_temp_header("Temperature(?C)"), None);
+        assert_eq!(is_temp_header("Temperature"), None);
+    }
+
+    #[test]
+    fn block_counts() {
+        for line in [
+            "##BLOCKS= 3",
+            "##BLOCKS=3",
+            "##BLOCKS=\t3",
+            "##BLOCKS= 3\r\n",
+        ] {
+            assert_eq!(get_block_count(line).unwrap(), 3, "{:?}", line);
+        }
+        assert!(get_block_count("##BLOCKS=").is_err());
+        assert!(get_block_count("##BLOCKS= three").is_err());
+        assert!(get_block_count("Plate:\tPlate1").is_err());
+    }
+
+    #[test]
+    fn temp_conversions() {
+        let body = r64(37.0);
+        assert_eq!(TempUnit::Celsius.convert(body), body);
+        assert!((TempUnit::Fahrenheit.convert(body) - 98.6).abs() < 1e-9);
+        assert!((TempUnit::Kelvin.convert(body) - 310.15).abs() < 1e-9);
+        for unit in [TempUnit::Celsius, TempUnit::Fahrenheit, TempUnit::Kelvin] {
+            assert!((unit.to_celsius(unit.convert(body)) - body).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn plate_sizes() {
+        for (size, (rows, cols)) in PLATE_SIZES {
+            assert_eq!(plate_dimensions(*size).unwrap(), (*rows, *cols));
+            assert_eq!(u32::from(*rows) * u32::from(*cols), *size);
+        }
+        assert_eq!(plate_dimensions(1536).unwrap(), (32, 48));
+    }
+
+    #[test]
+    fn unsupported_plate_size() {
+        let err = plate_dimensions(100).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unsupported plate size 100 (supported: 6, 12, 24, 48, 96, 384, 1536)"
+        );
+    }
 }
@@ -1,318 +1,1184 @@
-use anyhow::{self, Context};
-use encoding_rs::MACINTOSH;
+use anyhow::{anyhow, bail, Context};
+use clap::{Args, Parser, Subcommand};
+use encoding_rs::{Encoding, MACINTOSH, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
 use encoding_rs_io::DecodeReaderBytesBuilder;
-use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Write};
-use std::path::PathBuf;
-
-mod m5;
-mod output;
-mod utils;
-
-fn print_usage() {
-    println!("{} {}", env!("CARGO_BIN_NAME"), env!("CARGO_PKG_VERSION"));
-    println!("Convert Softmax M5(e) tab-delimited to flat CSV by well");
-    println!();
-    println!("Usage:");
-    println!("  {} <input> [output]", env!("CARGO_BIN_NAME"));
-    println!();
-    println!("  input           path to M5 tsv file");
-    println!("  [output]        path to output, or stdout if not present");
-}
-
-enum Args {
-    Help,
-    Missing,
-    Convert(PathBuf, Box<dyn Write>),
-}
-
-impl Args {
-    fn from_env() -> anyhow::Result<Self> {
-        let mut args = std::env::args().skip(1);
-        let input = args.next();
-        let output = args.next();
-
-        match input {
-            Some(s) if s == "-h" || s == "--help" => Ok(Self::Help),
-            None => Ok(Self::Missing),
-            Some(p) => {
-                let input = PathBuf::from(p);
-                let output = match output {
-                    Some(p) => {
-                        let f = File::create(PathBuf::from(p)).context("creating output file")?;
-                        Box::new(BufWriter::new(f)) as Box<dyn Write>
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use regex::Regex;
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, LineWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use m5conv::{
+    detect_encoding, read_to_blocks_magic, write_streams, Block, CsvWriter, JsonWriter,
+    KineticWideWriter, Layout, M5File, MatrixWriter, MultiWriter, NameTemplate, OdUnit,
+    Orientation, OutputOptions, ParseOptions, PlateSettings, QcWriter, ReadInfo, ReadWriter,
+    SortWriter, SplitWriter, SummaryWriter, TempUnit, TimeFormat, TotalsWriter, WarningSink,
+    Wavelength, WellCountWriter, WellSelection, WellValue, WellWideWriter, DEFAULT_BUFFER_SIZE,
+};
+
+#[derive(Clone, Copy, Default)]
+enum Format {
+    #[default]
+    Csv,
+    Tsv,
+    Json,
+    Ndjson,
+    Matrix,
+    KineticWide,
+    WellWide,
+    #[cfg(feature = "parquet")]
+    Parquet,
+    #[cfg(feature = "xlsx")]
+    Xlsx,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+    #[cfg(feature = "msgpack")]
+    Msgpack,
+    #[cfg(feature = "msgpack")]
+    MsgpackStream,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "tsv" => Ok(Self::Tsv),
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            "matrix" => Ok(Self::Matrix),
+            "kinetic-wide" => Ok(Self::KineticWide),
+            "well-wide" => Ok(Self::WellWide),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(Self::Parquet),
+            #[cfg(not(feature = "parquet"))]
+            "parquet" => Err(anyhow!("Built without parquet support")),
+            #[cfg(feature = "xlsx")]
+            "xlsx" => Ok(Self::Xlsx),
+            #[cfg(not(feature = "xlsx"))]
+            "xlsx" => Err(anyhow!("Built without xlsx support")),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(Self::Sqlite),
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => Err(anyhow!("Built without sqlite support")),
+            #[cfg(feature = "msgpack")]
+            "msgpack" => Ok(Self::Msgpack),
+            #[cfg(feature = "msgpack")]
+            "msgpack-stream" => Ok(Self::MsgpackStream),
+            #[cfg(not(feature = "msgpack"))]
+            "msgpack" | "msgpack-stream" => Err(anyhow!("Built without msgpack support")),
+            _ => Err(anyhow!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+impl Format {
+    /// If output can be added to the end of an earlier file and still be read as one: a
+    /// flat table whose header row can be left out, or a stream of records. The matrix and
+    /// wide formats write their header regardless
+    fn appendable(self) -> bool {
+        match self {
+            Self::Csv | Self::Tsv | Self::Ndjson => true,
+            #[cfg(feature = "msgpack")]
+            Self::MsgpackStream => true,
+            _ => false,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Csv | Self::Matrix | Self::KineticWide | Self::WellWide => "csv",
+            Self::Tsv => "tsv",
+            Self::Json => "json",
+            Self::Ndjson => "ndjson",
+            #[cfg(feature = "parquet")]
+            Self::Parquet => "parquet",
+            #[cfg(feature = "xlsx")]
+            Self::Xlsx => "xlsx",
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite => "sqlite",
+            #[cfg(feature = "msgpack")]
+            Self::Msgpack | Self::MsgpackStream => "msgpack",
+        }
+    }
+}
+
+#[derive(Clone)]
+enum SplitBy {
+    Plate,
+    Wavelength,
+    PlateWavelength,
+}
+
+/// A comma-separated list of `plate` and `wavelength`, with at least one of them
+impl FromStr for SplitBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mut plate, mut wavelength) = (false, false);
+        for split in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match split {
+                "plate" => plate = true,
+                "wavelength" => wavelength = true,
+                _ => bail!("Unknown split: {}", split),
+            }
+        }
+
+        Ok(match (plate, wavelength) {
+            (true, false) => Self::Plate,
+            (false, true) => Self::Wavelength,
+            (true, true) => Self::PlateWavelength,
+            (false, false) => bail!("Nothing to split by; give plate, wavelength, or both"),
+        })
+    }
+}
+
+struct Convert {
+    /// `None` reads from stdin
+    inputs: Vec<Option<PathBuf>>,
+    output: Option<PathBuf>,
+    format: Format,
+    opts: OutputOptions,
+    parse_opts: ParseOptions,
+    notes: Option<PathBuf>,
+    /// `Some("-")` writes the summary to stderr
+    summary: Option<PathBuf>,
+    /// `Some("-")` writes the QC metrics to stderr
+    qc: Option<PathBuf>,
+    /// `None` detects the encoding from the start of the input
+    encoding: Option<&'static Encoding>,
+    /// write a file per split into the `output` directory
+    split_by: Option<SplitBy>,
+    /// names the split files
+    name_template: Option<NameTemplate>,
+    gzip: bool,
+    /// add to the end of the output file rather than replacing it
+    append: bool,
+    /// parse without writing any output, reporting the file structure instead
+    check: bool,
+    /// parse without writing any output, printing totals to stdout instead
+    count: bool,
+    progress: bool,
+    /// report the wells written and empty cells skipped per plate on stderr
+    verbose: bool,
+    /// buffer the output to write it in a canonical order
+    sort: bool,
+}
+
+/// Convert Softmax M5(e) tab-delimited to flat CSV by well
+#[derive(Parser)]
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    override_usage = concat!(
+        env!("CARGO_BIN_NAME"), " [options] <input> [output]\n       ",
+        env!("CARGO_BIN_NAME"), " [options] <input> <input>... <output>\n       ",
+        env!("CARGO_BIN_NAME"), " check [options] [input]..."
+    ),
+    after_help = concat!(
+        "With several inputs, the last path is the output; the reads of each input are\n",
+        "written to it in turn, with an added Source File column.\n\n",
+        "Example:\n  cat plate.txt | ", env!("CARGO_BIN_NAME"), " - out.csv"
+    )
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    convert: ConvertArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert the inputs (the default without a subcommand)
+    Convert(Box<ConvertArgs>),
+    /// Only parse the inputs, printing each plate's settings and the block count to stderr
+    #[command(alias = "inspect")]
+    Check(CheckArgs),
+}
+
+/// How the inputs are read, shared by every subcommand
+#[derive(Args, Default)]
+struct ParseArgs {
+    /// Keep the blocks read before the input ends early (e.g. a missing final ~End)
+    /// instead of failing
+    #[arg(long)]
+    lenient: bool,
+    /// Fail if a read has more or fewer values than its read region's rows x cols x
+    /// wavelengths, e.g. a truncated grid
+    #[arg(long)]
+    strict_geometry: bool,
+    /// Fail on a NaN or inf well value instead of writing it with a Non-finite status
+    #[arg(long)]
+    strict_values: bool,
+    /// Parse numbers written with a decimal comma (1,5); output always uses a decimal point
+    #[arg(long)]
+    decimal_comma: bool,
+    /// Input text encoding: macroman, utf8, latin1, utf16 (little-endian), or utf16be;
+    /// detected from the input if not given
+    #[arg(long, value_name = "e", value_parser = parse_encoding)]
+    encoding: Option<&'static Encoding>,
+    /// Only output plates whose name contains name, or matches it as a glob with * and ?;
+    /// other plates aren't parsed. Repeatable
+    #[arg(long, value_name = "name")]
+    plate: Vec<String>,
+    /// Output empty (masked) wells in the read region instead of skipping them
+    #[arg(long)]
+    emit_empty: bool,
+    /// Layout of the read grids: row-major (a line per plate row), or col-major (a line per
+    /// plate column, as in some well scan exports)
+    #[arg(long, value_name = "o", default_value = "row-major")]
+    orientation: Orientation,
+    /// Plate size to use for every plate instead of the one in its settings (6, 12, 24,
+    /// 48, 96, 384, or 1536 wells), for a plate size that's wrong or missing
+    #[arg(long, value_name = "wells")]
+    plate_size: Option<u32>,
+    /// Write warnings to path as JSON lines (an object per warning, with its kind, message,
+    /// and plate, well, line, etc.) instead of to stderr
+    #[arg(long, value_name = "path")]
+    warnings_json: Option<PathBuf>,
+}
+
+#[derive(Args, Default)]
+struct ConvertArgs {
+    /// Paths to M5 tsv files, or - to read from stdin (gzipped input is decompressed),
+    /// then the output path, or - for stdout
+    #[arg(value_name = "input")]
+    paths: Vec<String>,
+    /// Output format: csv, tsv, json, ndjson, matrix, kinetic-wide (a row per well and
+    /// wavelength, with a column per read time), well-wide (a row per well and read, with
+    /// a column per wavelength), parquet, xlsx, or sqlite (a readings
+    /// table; all three require an output path), msgpack (the plates as one MessagePack
+    /// value), or msgpack-stream (one MessagePack map per well)
+    #[arg(long, value_name = "fmt", default_value = "csv")]
+    format: Format,
+    /// Read times as decimal hours or clock (HH:MM:SS); csv, tsv, matrix, and kinetic-wide
+    /// output only
+    #[arg(long, value_name = "f", default_value = "hours")]
+    time_format: TimeFormat,
+    /// Write each plate's read times relative to its earliest read, e.g. for kinetic reads
+    /// timed from the instrument clock; the Raw Time column is left as exported
+    #[arg(long)]
+    relative_time: bool,
+    /// Add a Raw Time column with read times as written in the input; csv, tsv, and xlsx
+    /// output only
+    #[arg(long)]
+    raw_time: bool,
+    /// Add a Raw Value column with well values as written in the input, e.g. integer
+    /// counts as printed; csv, tsv, and xlsx output only
+    #[arg(long)]
+    raw_value: bool,
+    /// Add an Experiment column with the index of each plate's export, for inputs of
+    /// several exports concatenated together; csv, tsv, and xlsx output only
+    #[arg(long)]
+    experiment: bool,
+    /// Add a Block column with the position of each plate's block in its export; csv, tsv,
+    /// and xlsx output only
+    #[arg(long)]
+    block_index: bool,
+    /// Add Read Type and Read Pattern (well scan reads only) columns; csv, tsv, and xlsx
+    /// output only
+    #[arg(long)]
+    read_type: bool,
+    /// Add Point Row and Point Col columns with each value's point in its well, for well
+    /// scans of several points per well; csv, tsv, and xlsx output only
+    #[arg(long)]
+    scan_points: bool,
+    /// Add a Value Unit column with the unit (OD or mOD) of absorbance values; csv, tsv,
+    /// and xlsx output only
+    #[arg(long)]
+    value_unit: bool,
+    /// Convert absorbance values to od or mod, for inputs mixing the two
+    #[arg(long, value_name = "u")]
+    od_unit: Option<OdUnit>,
+    /// Temperature unit: c, f, or k
+    #[arg(long, value_name = "u", default_value = "c")]
+    temp_unit: TempUnit,
+    /// Write values, temperatures, and decimal-hour times with n decimal places; csv,
+    /// tsv, and matrix output only
+    #[arg(long, value_name = "n")]
+    precision: Option<usize>,
+    /// Write s in empty csv/tsv cells (e.g. the time of an endpoint read) instead of
+    /// leaving them blank, e.g. \N
+    #[arg(long, value_name = "s")]
+    null_value: Option<String>,
+    /// Write each plate block to its own file, named after the plate, in the output
+    /// directory; or each wavelength (e.g. 600nm.csv), from every plate; or with
+    /// plate,wavelength, each plate's wavelengths (Plate1_600nm.csv)
+    #[arg(long, value_name = "plate|wavelength")]
+    split_by: Option<SplitBy>,
+    /// Name each split file by this template, with {plate}, {mode}, {block},
+    /// {wavelength}, and {source} (input file) filled in, e.g. "{source}_{block}_{plate}"
+    #[arg(long, value_name = "t", requires = "split_by")]
+    name_template: Option<NameTemplate>,
+    /// Gzip compress the output; implied by a .gz output path
+    #[arg(long)]
+    gzip: bool,
+    /// Add to the end of the output file instead of replacing it, without a csv/tsv
+    /// header row if the file already has content; csv, tsv, ndjson, and msgpack-stream
+    /// output only
+    #[arg(long)]
+    append: bool,
+    /// Replace an existing sqlite output database; sqlite output otherwise fails rather
+    /// than delete it. Other formats always replace their output file
+    #[arg(long)]
+    overwrite: bool,
+    #[command(flatten)]
+    parse: ParseArgs,
+    /// Only parse the inputs, as the check subcommand does; every path is an input
+    #[arg(long)]
+    check: bool,
+    /// Only parse the inputs, printing the total blocks, reads, wells, wavelengths, and
+    /// plates to stdout; every path is an input. Reads and wells are those that would be
+    /// written, after --wells, --wavelength, etc.
+    #[arg(long)]
+    count: bool,
+    /// Show how much of each input file has been read, when writing to an output path and
+    /// stderr is a terminal
+    #[arg(long)]
+    progress: bool,
+    /// Also write the text of any note blocks to path
+    #[arg(long, value_name = "path")]
+    notes: Option<PathBuf>,
+    /// Also write one CSV row of settings (read type and mode, size, reads, wavelengths)
+    /// per plate to path, or - for stderr
+    #[arg(long, value_name = "path")]
+    summary: Option<PathBuf>,
+    /// Also write the mean, SD, %CV, min, and max of each plate column and the whole
+    /// plate, per read and wavelength, to path, or - for stderr
+    #[arg(long, value_name = "path")]
+    qc: Option<PathBuf>,
+    /// Positive control wells (same syntax as --wells); with --blank, adds a Z-factor to
+    /// the --qc output
+    #[arg(long, value_name = "spec")]
+    positive_control: Vec<WellSelection>,
+    /// Only output this wavelength, as ex/em (485/535) for fluorescence, ex/em/p or
+    /// ex/em/s for a polarization channel (ex/em/mp for --fp-mp), nm (600) for absorbance,
+    /// or nmpc (977pc) for a PathCheck reference; repeatable
+    #[arg(long, value_name = "w")]
+    wavelength: Vec<Wavelength>,
+    /// Only output reads at or after this time; reads without a time (e.g. endpoint) are
+    /// dropped
+    #[arg(long, value_name = "hr")]
+    time_min: Option<f64>,
+    /// Only output reads at or before this time
+    #[arg(long, value_name = "hr")]
+    time_max: Option<f64>,
+    /// Only output wells with a value (after --blank) of at least v; overflowed and masked
+    /// wells are dropped
+    #[arg(long, value_name = "v")]
+    value_min: Option<f64>,
+    /// Only output wells with a value of at most v
+    #[arg(long, value_name = "v")]
+    value_max: Option<f64>,
+    /// With --value-min/--value-max, keep overflowed wells, counting them as above any
+    /// value
+    #[arg(long)]
+    include_overflow: bool,
+    /// Only output these wells: a comma-separated list of wells (A1), ranges (A1-H12), rows
+    /// (row:C), or columns (col:5)
+    #[arg(long, value_name = "spec")]
+    wells: Vec<WellSelection>,
+    /// Split plate names into the Plate column (capture group 1) and an added Run column
+    /// (group 2); names that don't match are kept whole. csv, tsv, and xlsx output only
+    #[arg(long, value_name = "regex")]
+    split_plate_name: Option<Regex>,
+    /// Only write these csv/tsv columns, in this order, from: experiment, block, source,
+    /// plate, run, well, row, col, point_row, point_col, time, raw_time, temperature,
+    /// read_type, read_pattern, mode, excitation, emission, description, value, raw_value,
+    /// value_unit, status, delay, integration, normalized, corrected_od, sample
+    #[arg(long, value_name = "list")]
+    columns: Option<String>,
+    /// Comma-separated names for the header row, replacing the written columns' own names
+    /// in order (one per column); csv, tsv, and xlsx output only
+    #[arg(long, value_name = "list")]
+    header: Option<String>,
+    /// CSV with Well and Sample columns; adds a Sample column to csv/tsv output
+    #[arg(long, value_name = "path")]
+    layout: Option<PathBuf>,
+    /// Subtract the mean of these wells (same syntax as --wells) from each value of the
+    /// same read and wavelength; the Value column is then blank corrected. Repeatable
+    #[arg(long, value_name = "spec")]
+    blank: Vec<WellSelection>,
+    /// Divide each value (after --blank) by the mean of these wells of the same read and
+    /// wavelength, in an added Normalized column of csv, tsv, json, and parquet output.
+    /// Repeatable
+    #[arg(long, value_name = "spec")]
+    normalize_to: Vec<WellSelection>,
+    /// Replace the parallel and perpendicular values of FP reads with each well's mP,
+    /// 1000 * (P - G*S) / (P + G*S)
+    #[arg(long)]
+    fp_mp: bool,
+    /// G factor for --fp-mp [default: 1]
+    #[arg(long, value_name = "g", requires = "fp_mp")]
+    g_factor: Option<f64>,
+    /// Add a Corrected OD column to absorbance output, with values corrected to a 1 cm
+    /// path by the 900 and 977 (or 1000) nm reference reads: A * k / (A977 - A900)
+    #[arg(long)]
+    pathlength_correct: bool,
+    /// k for --pathlength-correct [default: 0.18]
+    #[arg(long, value_name = "k", requires = "pathlength_correct")]
+    water_constant: Option<f64>,
+    /// Stop after writing n wells, without parsing the rest of the input
+    #[arg(long, value_name = "n")]
+    limit: Option<usize>,
+    /// Buffer this many bytes of csv, tsv, or matrix output before writing it out [default:
+    /// 8192]; a larger buffer (e.g. 1048576) writes to a network filesystem in fewer, bigger
+    /// writes
+    #[arg(long, value_name = "bytes", value_parser = clap::value_parser!(usize))]
+    buffer_size: Option<usize>,
+    /// Write wells sorted by plate, wavelength, time, row, then column; matrix and
+    /// well-wide output, which give each wavelength of a read its own grid or column, are
+    /// sorted by plate, time, row, then column. This holds the whole output in memory until
+    /// the inputs have been read, instead of streaming it
+    #[arg(long)]
+    sort: bool,
+    /// After converting, report how many wells were written and empty cells skipped for
+    /// each plate on stderr
+    #[arg(long)]
+    verbose: bool,
+}
+
+#[derive(Args)]
+struct CheckArgs {
+    /// Paths to M5 tsv files, or - to read from stdin
+    #[arg(value_name = "input")]
+    inputs: Vec<String>,
+    #[command(flatten)]
+    parse: ParseArgs,
+}
+
+impl From<CheckArgs> for ConvertArgs {
+    fn from(args: CheckArgs) -> Self {
+        Self {
+            paths: args.inputs,
+            parse: args.parse,
+            check: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Merge the selections of a repeatable well option
+fn merge_wells(selections: Vec<WellSelection>) -> Option<WellSelection> {
+    let mut selections = selections.into_iter();
+    let mut merged = selections.next()?;
+    selections.for_each(|wells| merged.extend(wells));
+    Some(merged)
+}
+
+impl ConvertArgs {
+    /// `None` if there's no input to read
+    fn into_convert(self) -> anyhow::Result<Option<Convert>> {
+        let Self {
+            mut paths,
+            format,
+            time_format,
+            relative_time,
+            raw_time,
+            raw_value,
+            experiment,
+            block_index,
+            read_type,
+            scan_points,
+            value_unit,
+            od_unit,
+            temp_unit,
+            precision,
+            null_value,
+            split_by,
+            name_template,
+            gzip,
+            append,
+            overwrite,
+            parse,
+            check,
+            count,
+            progress,
+            notes,
+            summary,
+            qc,
+            positive_control,
+            wavelength,
+            time_min,
+            time_max,
+            value_min,
+            value_max,
+            include_overflow,
+            wells,
+            split_plate_name,
+            columns,
+            header,
+            layout,
+            blank,
+            normalize_to,
+            fp_mp,
+            g_factor,
+            pathlength_correct,
+            water_constant,
+            limit,
+            buffer_size,
+            sort,
+            verbose,
+        } = self;
+
+        let warnings = match &parse.warnings_json {
+            Some(path) => {
+                let log = File::create(path)
+                    .with_context(|| anyhow!("creating warning log {}", path.display()))?;
+                WarningSink::json(Box::new(LineWriter::new(log)))
+            }
+            None => WarningSink::default(),
+        };
+        let parse_opts = ParseOptions {
+            emit_empty: parse.emit_empty,
+            lenient: parse.lenient,
+            decimal_comma: parse.decimal_comma,
+            plates: parse.plate,
+            strict_geometry: parse.strict_geometry,
+            strict_values: parse.strict_values,
+            orientation: parse.orientation,
+            relative_time,
+            plate_size: parse.plate_size,
+            raw_values: raw_value,
+            warnings,
+        };
+        if let Some(re) = &split_plate_name {
+            if re.captures_len() < 2 {
+                bail!("--split-plate-name needs a capture group for the plate name");
+            }
+        }
+        let columns = match columns {
+            Some(columns) => columns
+                .split(',')
+                .filter(|name| !name.trim().is_empty())
+                .map(str::parse)
+                .collect::<anyhow::Result<_>>()
+                .context("parsing --columns")?,
+            None => Vec::new(),
+        };
+        let header = header
+            .map(|header| header.split(',').map(|h| h.trim().to_string()).collect())
+            .unwrap_or_default();
+        let layout = match layout {
+            Some(path) => Some(Layout::from_path(&path)?),
+            None => None,
+        };
+        let mut opts = OutputOptions {
+            temp_unit,
+            time_format,
+            wavelengths: wavelength,
+            time_min,
+            time_max,
+            wells: merge_wells(wells),
+            value_min,
+            value_max,
+            include_overflow,
+            layout,
+            blank: merge_wells(blank),
+            normalize_to: merge_wells(normalize_to),
+            positive_control: merge_wells(positive_control),
+            raw_time,
+            raw_value,
+            experiment,
+            block_index,
+            read_type,
+            scan_points,
+            value_unit,
+            od_unit,
+            fp_mp: Some(g_factor.unwrap_or(1.0)).filter(|_| fp_mp),
+            pathlength_k: Some(water_constant.unwrap_or(0.18)).filter(|_| pathlength_correct),
+            precision,
+            null_value: null_value.unwrap_or_default(),
+            limit,
+            buffer_size,
+            overwrite,
+            plate_name_pattern: split_plate_name,
+            columns,
+            header,
+            warnings: parse_opts.warnings.clone(),
+            ..Default::default()
+        };
+
+        // the last of several paths is the output
+        let output = match paths.len() {
+            0 | 1 => None,
+            _ if check || count => None,
+            // - writes to stdout, e.g. to pipe a merge of several inputs
+            _ => paths.pop().filter(|p| p != "-").map(PathBuf::from),
+        };
+        let inputs = paths
+            .iter()
+            .map(|p| Some(p).filter(|&p| p != "-").map(PathBuf::from))
+            .collect::<Vec<_>>();
+        let inputs = match inputs.is_empty() {
+            // with no input path, read from stdin if something is being piped in
+            true if io::stdin().is_terminal() => return Ok(None),
+            true => vec![None],
+            false => inputs,
+        };
+        if inputs.iter().filter(|i| i.is_none()).count() > 1 {
+            bail!("stdin (-) can only be read once");
+        }
+        opts.source_files = inputs.len() > 1;
+        if split_by.is_some() && output.is_none() {
+            bail!("--split-by requires an output directory");
+        }
+        // a single .gz output file implies --gzip
+        let gzip = gzip
+            || (split_by.is_none()
+                && matches!(&output, Some(p) if p.extension() == Some("gz".as_ref())));
+        #[cfg(feature = "parquet")]
+        {
+            if gzip && matches!(format, Format::Parquet) {
+                bail!("--gzip isn't supported for parquet output, which is already compressed");
+            }
+        }
+        #[cfg(feature = "parquet")]
+        {
+            if matches!(format, Format::Parquet) && output.is_none() {
+                bail!("--format parquet requires an output path");
+            }
+        }
+        #[cfg(feature = "xlsx")]
+        {
+            if matches!(format, Format::Xlsx) {
+                if output.is_none() {
+                    bail!("--format xlsx requires an output path");
+                }
+                if gzip {
+                    bail!("--gzip isn't supported for xlsx output, which is already compressed");
+                }
+            }
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            if matches!(format, Format::Sqlite) {
+                if output.is_none() {
+                    bail!("--format sqlite requires an output path");
+                }
+                if gzip {
+                    bail!("--gzip isn't supported for sqlite output");
+                }
+                if let (None, Some(path), false) = (&split_by, &output, overwrite) {
+                    if path.exists() {
+                        bail!(
+                            "Output database {} already exists; pass --overwrite to replace it",
+                            path.display()
+                        );
                     }
-                    None => Box::new(io::stdout()) as Box<dyn Write>,
-                };
-                Ok(Self::Convert(input, output))
+                }
             }
         }
+        if append {
+            if output.is_none() || split_by.is_some() {
+                bail!("--append requires a single output file");
+            }
+            if !format.appendable() {
+                bail!("--append is only supported for csv, tsv, ndjson, and msgpack-stream output");
+            }
+            // an empty file still needs its header
+            opts.no_header = output
+                .as_deref()
+                .and_then(|p| std::fs::metadata(p).ok())
+                .is_some_and(|m| m.len() > 0);
+        }
+
+        Ok(Some(Convert {
+            inputs,
+            output,
+            format,
+            opts,
+            parse_opts,
+            notes,
+            summary,
+            qc,
+            encoding: parse.encoding,
+            split_by,
+            name_template,
+            gzip,
+            append,
+            check,
+            count,
+            progress,
+            verbose,
+            sort,
+        }))
+    }
+}
+
+/// Enough to reach the first plate's temperature header, past the settings row
+const ENCODING_PEEK_LEN: usize = 4096;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+fn parse_encoding(label: &str) -> anyhow::Result<&'static Encoding> {
+    match label.to_ascii_lowercase().as_str() {
+        "macroman" | "macintosh" | "mac" => Ok(MACINTOSH),
+        "utf8" | "utf-8" => Ok(UTF_8),
+        "latin1" | "windows-1252" | "cp1252" => Ok(WINDOWS_1252),
+        "utf16" | "utf-16" | "utf16le" | "utf-16le" => Ok(UTF_16LE),
+        "utf16be" | "utf-16be" => Ok(UTF_16BE),
+        _ => bail!("Unknown input encoding: {}", label),
     }
 }
 
 fn main() -> anyhow::Result<()> {
-    let args = Args::from_env().context("parsing args")?;
+    let cli = Cli::parse();
+    let args = match cli.command {
+        Some(Command::Convert(args)) => *args,
+        Some(Command::Check(args)) => args.into(),
+        None => cli.convert,
+    };
 
-    match args {
-        Args::Help => print_usage(),
-        Args::Missing => {
+    match args.into_convert().context("parsing args")? {
+        Some(convert) => parse_input(convert)?,
+        None => {
             eprintln!("Missing input M5 tab-delimited file");
             eprintln!("Pass --help for more info");
         }
-        Args::Convert(input, output) => {
-            parse_input(input, output)?;
+    }
+
+    Ok(())
+}
+
+fn parse_input(convert: Convert) -> anyhow::Result<()> {
+    let Convert {
+        inputs,
+        output,
+        format,
+        opts,
+        parse_opts,
+        notes,
+        summary,
+        qc,
+        encoding,
+        split_by,
+        name_template,
+        gzip,
+        append,
+        check,
+        count,
+        progress,
+        verbose,
+        sort,
+    } = convert;
+    if count {
+        return count_inputs(&inputs, encoding, &parse_opts, &opts);
+    }
+    // the bar would be mixed in with output to stdout
+    let progress = progress && output.is_some() && io::stderr().is_terminal();
+    let names = inputs.iter().map(input_name).collect::<Vec<_>>();
+    // inputs are only opened once the previous one has been written
+    let mut inputs = inputs.into_iter().zip(&names).map(|(input, name)| {
+        let progress = Some(name.as_str()).filter(|_| progress);
+        let rdr = open_input(input.as_deref(), encoding, progress)
+            .with_context(|| anyhow!("opening input {}", name))?;
+        let rdr = check_export(rdr).with_context(|| anyhow!("reading input {}", name))?;
+        Ok((name.clone(), rdr))
+    });
+    // except the first, so input that isn't an export fails before any output is written
+    let first = inputs.next().transpose()?;
+    // reads are written out as they're parsed, rather than holding the whole file
+    let wtr: Box<dyn ReadWriter> = match (split_by, output) {
+        _ if check => Box::new(SummaryWriter::new(Box::new(io::stderr()))?),
+        (Some(split_by), Some(dir)) => {
+            std::fs::create_dir_all(&dir).context("creating output directory")?;
+            let opts = &opts;
+            let mut extension = format.extension().to_string();
+            if gzip {
+                extension.push_str(".gz");
+            }
+            let split = SplitWriter::new(
+                dir,
+                extension,
+                Box::new(move |path| open_writer(format, Some(path), gzip, false, opts)),
+            );
+            let split = match split_by {
+                SplitBy::Plate => split,
+                SplitBy::Wavelength => split.by_wavelength(false),
+                SplitBy::PlateWavelength => split.by_wavelength(true),
+            };
+            match name_template {
+                Some(template) => Box::new(split.with_template(template)),
+                None => Box::new(split),
+            }
+        }
+        (_, output) => open_writer(format, output.as_deref(), gzip, append, &opts)?,
+    };
+    let wtr = match sort {
+        true => match format {
+            Format::Matrix | Format::WellWide => Box::new(SortWriter::new(wtr).whole_reads()),
+            _ => Box::new(SortWriter::new(wtr)),
+        },
+        false => wtr,
+    };
+    let mut writers = vec![wtr];
+    if let Some(path) = summary {
+        let out = open_sidecar(&path).context("creating summary file")?;
+        writers.push(Box::new(SummaryWriter::new(out)?));
+    }
+    if let Some(path) = qc {
+        let out = open_sidecar(&path).context("creating QC file")?;
+        writers.push(Box::new(QcWriter::new(out, &opts)?));
+    }
+    if verbose {
+        writers.push(Box::new(WellCountWriter::new(Box::new(io::stderr()))));
+    }
+    let mut wtr = match writers.len() {
+        1 => writers.pop().unwrap(),
+        _ => Box::new(MultiWriter::new(writers)),
+    };
+    let inputs = first.map(Ok).into_iter().chain(inputs);
+    let files = write_streams(inputs, &parse_opts, wtr.as_mut(), &opts).context(if check {
+        "checking input"
+    } else {
+        "writing output"
+    })?;
+
+    if check {
+        for (name, file) in names.iter().zip(&files) {
+            let count = |f: fn(&Block) -> bool| file.blocks.iter().filter(|&b| f(b)).count();
+            eprintln!(
+                "{}: {} blocks ({} plates, {} groups, {} notes)",
+                name,
+                file.blocks.len(),
+                count(|b| matches!(b, Block::Plate(_))),
+                count(|b| matches!(b, Block::Group(_))),
+                count(|b| matches!(b, Block::Note(_))),
+            );
+            for line in file.metadata() {
+                eprintln!("  {}", line);
+            }
         }
     }
 
+    if let Some(path) = notes {
+        write_notes(&files, &path).context("writing notes")?;
+    }
+
+    // anything still buffered for stdout, rather than losing a failure when it's dropped
+    io::stdout().flush().context("flushing output")?;
     Ok(())
 }
 
-fn parse_input(path: PathBuf, output: Box<dyn Write>) -> anyhow::Result<()> {
-    // output text file seems to be in macroman encoding..? Just for the degree symbol...
-    let decoder = DecodeReaderBytesBuilder::new()
-        .encoding(Some(MACINTOSH))
-        .build(File::open(path)?);
-    let rdr = BufReader::new(decoder);
+/// The file name of an input, or stdin
+fn input_name(input: &Option<PathBuf>) -> String {
+    let name = match input {
+        Some(path) => path.file_name().unwrap_or(path.as_os_str()),
+        None => "stdin".as_ref(),
+    };
+    name.to_string_lossy().into_owned()
+}
 
-    let file = m5::M5File::read_and_parse(rdr)?;
+/// Stream the inputs through a [`TotalsWriter`], printing its totals to stdout
+fn count_inputs(
+    inputs: &[Option<PathBuf>],
+    encoding: Option<&'static Encoding>,
+    parse_opts: &ParseOptions,
+    opts: &OutputOptions,
+) -> anyhow::Result<()> {
+    let mut totals = TotalsWriter::new();
+    // checked as the convert path checks them, so they fail the same way
+    let mut inputs = inputs.iter().map(|input| {
+        let name = input_name(input);
+        let rdr = open_input(input.as_deref(), encoding, None)
+            .with_context(|| anyhow!("opening input {}", name))?;
+        let rdr = check_export(rdr).with_context(|| anyhow!("reading input {}", name))?;
+        Ok((name, rdr))
+    });
+    let first = inputs.next().transpose()?;
+    let inputs = first.map(Ok).into_iter().chain(inputs);
+    let files = write_streams(inputs, parse_opts, &mut totals, opts).context("counting input")?;
 
-    output::write_csv(file, output).context("writing to output csv")
+    let blocks = files.iter().map(|f| f.blocks.len()).sum();
+    totals.write_table(io::stdout().lock(), blocks)
 }
 
-/*
-fn parse_input<'a>(path: PathBuf, output: Box<dyn Write>) -> anyhow::Result<()> {
-    // output text file seems to be in macroman encoding..? Just for the degree symbol...
+/// Read an input as far as its `##BLOCKS=` line, to check it's an export, then put the lines
+/// read back in front of the rest
+fn check_export(mut rdr: Box<dyn BufRead>) -> anyhow::Result<Box<dyn BufRead>> {
+    let start = read_to_blocks_magic(&mut rdr)?;
+    Ok(Box::new(io::Cursor::new(start).chain(rdr)))
+}
+
+/// Decode an input, or stdin if `path` is `None`.
+///
+/// Gzipped input (a .gz path, or starting with the gzip magic number) is decompressed
+/// first. Older Softmax versions export MacRoman (only matters for the degree symbol), but
+/// newer ones may write UTF-8 or Windows-1252; a BOM overrides the chosen encoding.
+///
+/// With `progress` (the input's name), a file input shows a progress bar on stderr
+fn open_input(
+    path: Option<&Path>,
+    encoding: Option<&'static Encoding>,
+    progress: Option<&str>,
+) -> anyhow::Result<Box<dyn BufRead>> {
+    let input: Box<dyn Read> = match (path, progress) {
+        (Some(path), Some(name)) => {
+            let file = File::open(path).context("opening input file")?;
+            let len = file.metadata().context("reading input file size")?.len();
+            Box::new(Progress::new(file, len, name))
+        }
+        (Some(path), None) => Box::new(File::open(path).context("opening input file")?),
+        (None, _) => Box::new(io::stdin().lock()),
+    };
+    let mut input = BufReader::new(input);
+    let gzipped = input
+        .fill_buf()
+        .context("reading start of input")?
+        .starts_with(&GZIP_MAGIC);
+    if !gzipped && matches!(path, Some(p) if p.extension() == Some("gz".as_ref())) {
+        bail!("Input has a .gz extension, but isn't gzip compressed");
+    }
+    let mut input: Box<dyn Read> = match gzipped {
+        true => Box::new(MultiGzDecoder::new(input)),
+        false => Box::new(input),
+    };
+    let encoding = match encoding {
+        Some(encoding) => encoding,
+        None => {
+            let mut peek = Vec::with_capacity(ENCODING_PEEK_LEN);
+            input
+                .by_ref()
+                .take(ENCODING_PEEK_LEN as u64)
+                .read_to_end(&mut peek)
+                .context("reading start of input")?;
+            let encoding = detect_encoding(&peek);
+            input = Box::new(io::Cursor::new(peek).chain(input));
+            encoding
+        }
+    };
     let decoder = DecodeReaderBytesBuilder::new()
-        .encoding(Some(MACINTOSH))
-        .build(File::open(path)?);
-    let mut rdr = BufReader::new(decoder);
-    let mut buf = String::with_capacity(0x100);
-
-    rdr.read_line(&mut buf).context("reading block count")?;
-    let block_count = m5::M5BlockCount::from_str(&buf).context("parsing initial blocks count")?;
-    //println!("{:?}", block_count);
-    buf.clear();
-
-    // loop for multiple blocks here?
-    rdr.read_line(&mut buf).context("reading plate info")?;
-    let settings = parse_settings(&buf).context("parsing plate info")?;
-    //println!("{:#?}", settings);
-    buf.clear();
-
-    rdr.read_line(&mut buf)
-        .context("reading temp. and plate col header line")?;
-    match buf.split('\t').nth(1) {
-        Some("Temperature(°C)") => (),
-        Some(unk) => bail!("Unknown/unsupported temperature unit: {}", unk),
-        None => bail!("Couldn't read temperature and plate headers:\n{}", &buf),
+        .encoding(Some(encoding))
+        .bom_override(true)
+        .build(input);
+
+    Ok(Box::new(BufReader::new(decoder)))
+}
+
+/// Shows how much of a file has been read, as a percentage on stderr.
+///
+/// This counts the file's own bytes, before any gunzipping or text decoding, so it
+/// matches the file size
+struct Progress<R> {
+    inner: R,
+    name: String,
+    len: u64,
+    read: u64,
+    /// last percentage shown
+    shown: Option<u64>,
+}
+
+impl<R> Progress<R> {
+    fn new(inner: R, len: u64, name: &str) -> Self {
+        Self {
+            inner,
+            name: name.to_string(),
+            len,
+            read: 0,
+            shown: None,
+        }
     }
-    buf.clear();
-
-    let plate_data = parse_plate(&mut rdr, &mut buf, &settings)?;
-
-    //write_data(plate_data, output).context("writing output CSV")
-    output::write_data(plate_data, output).context("writing output CSV")
-}
-
-fn parse_plate<'p, R: BufRead>(
-    mut rdr: R,
-    buf: &mut String,
-    settings: &'p PlateSettings,
-) -> anyhow::Result<Vec<WellValue<'p>>> {
-    let total_wells =
-        settings.wavelengths.len() * (settings.cols as usize) * (settings.rows as usize);
-    let mut output = Vec::with_capacity(total_wells);
-    //let mut plate_buf = Vec::with_capacity(plate_cols);
-
-    let mut time = None;
-    let mut temp = None;
-
-    // loop for multiple time points here?
-    for r in 0..settings.rows {
-        //plate_buf.clear();
-        buf.clear();
-        rdr.read_line(buf)?;
-
-        let mut line = buf.split('\t');
-        let new_time = line
-            .next()
-            .ok_or(anyhow::anyhow!("no time column"))
-            .and_then(get_time)
-            .context(format!("couldn't parse time, {}", &buf))?;
-        let new_temp = line
-            .next()
-            .ok_or(anyhow::anyhow!("no temp column"))
-            .and_then(get_temp)
-            .context("couldn't parse temperaute")?;
-
-        time = time.or(new_time);
-        temp = temp.or(new_temp);
-
-        // borrow checker
-        let plate_buf: Vec<_> = line.collect();
-        //let plate_iter =
-
-        plate_buf
-            .chunks(settings.cols as usize + 1)
-            .zip(settings.wavelengths.iter().copied())
-            .flat_map(|(plate, wavelength)| {
-                let (plate, spacer) = plate.split_at(settings.cols as usize);
-
-                plate
-                    .iter()
-                    .copied()
-                    .map(str::trim)
-                    .enumerate()
-                    .filter(|(_, s)| !s.is_empty())
-                    .map(move |(c, value)| {
-                        value
-                            .parse()
-                            .context("parsing well value")
-                            .map(|value| WellValue {
-                                plate: &settings.name,
-                                temp: temp.expect("temp value before wells"),
-                                time: time.expect("time value before wells"),
-                                wavelength,
-                                well: (r, c as u8),
-                                value,
-                            })
-                    })
-            })
-            .try_for_each::<_, anyhow::Result<()>>(|val| {
-                let val = val.context("issue parsing well value")?;
-
-                output.push(val);
-
-                Ok(())
-            })?;
+}
+
+impl<R: Read> Read for Progress<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+
+        let percent = (self.read * 100)
+            .checked_div(self.len)
+            .unwrap_or(100)
+            .min(100);
+        if self.shown != Some(percent) {
+            eprint!("\r{}: {:3}%", self.name, percent);
+            self.shown = Some(percent);
+        }
+
+        Ok(n)
     }
+}
 
-    buf.clear();
-    rdr.read_line(buf)?;
-    // check that this line is empty
-    //println!("empty line?\n'{}'", &buf);
+impl<R> Drop for Progress<R> {
+    fn drop(&mut self) {
+        // end the progress line, even if the input wasn't read to the end
+        if self.shown.is_some() {
+            eprintln!();
+        }
+    }
+}
 
-    //println!("Time: {:?}hr and Temp: {:?}C", time, temp)
+fn open_writer<'a>(
+    format: Format,
+    output: Option<&Path>,
+    gzip: bool,
+    append: bool,
+    opts: &'a OutputOptions,
+) -> anyhow::Result<Box<dyn ReadWriter + 'a>> {
+    // kept to finish (flushing its buffer, and writing any gzip trailer) once the writer has
+    let mut opened = None;
+    let mut open = || -> anyhow::Result<Box<dyn Write>> {
+        let out = open_output(output, gzip, append, opts.buffer_size)?;
+        opened = Some(out.clone());
+        Ok(Box::new(out))
+    };
+    let wtr: Box<dyn ReadWriter> = match format {
+        Format::Csv => Box::new(CsvWriter::new(open()?, b',', opts)?),
+        Format::Tsv => Box::new(CsvWriter::new(open()?, b'\t', opts)?),
+        Format::Json => Box::new(JsonWriter::array(open()?, opts)?),
+        Format::Ndjson => Box::new(JsonWriter::lines(open()?, opts)),
+        Format::Matrix => Box::new(MatrixWriter::new(open()?, opts)),
+        Format::KineticWide => Box::new(KineticWideWriter::new(open()?, opts)),
+        Format::WellWide => Box::new(WellWideWriter::new(open()?, opts)),
+        #[cfg(feature = "parquet")]
+        Format::Parquet => {
+            let path = output.context("parquet output requires an output path")?;
+            Box::new(m5conv::ParquetWriter::new(path, opts)?)
+        }
+        #[cfg(feature = "xlsx")]
+        Format::Xlsx => {
+            let path = output.context("xlsx output requires an output path")?;
+            Box::new(m5conv::XlsxWriter::new(path, opts)?)
+        }
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => {
+            let path = output.context("sqlite output requires an output path")?;
+            Box::new(m5conv::SqliteWriter::new(path, opts)?)
+        }
+        #[cfg(feature = "msgpack")]
+        Format::Msgpack => Box::new(m5conv::MsgpackWriter::file(open()?, opts)),
+        #[cfg(feature = "msgpack")]
+        Format::MsgpackStream => Box::new(m5conv::MsgpackWriter::records(open()?, opts)),
+    };
 
-    //line.next().ok_or(anyhow::anyhow!("missing spacer column")).and_then(check_spacer)?;
+    match opened {
+        Some(output) => Ok(Box::new(FinishOutput { inner: wtr, output })),
+        None => Ok(wtr),
+    }
+}
 
-    Ok(output)
+/// Finishes `output` after `inner` has written the last of it
+struct FinishOutput<'a> {
+    inner: Box<dyn ReadWriter + 'a>,
+    output: Output,
 }
 
-fn check_spacer(s: &str) -> anyhow::Result<()> {
-    if s.is_empty() {
-        Ok(())
-    } else {
-        Err(anyhow::anyhow!("non-empty spacer column: {}", s))
+impl ReadWriter for FinishOutput<'_> {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> anyhow::Result<()> {
+        self.inner.write_read(settings, read_info, wells)
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.inner.finish()?;
+        self.output.finish().context("finishing output")
+    }
+
+    fn start_plate(&mut self, settings: &PlateSettings) -> anyhow::Result<()> {
+        self.inner.start_plate(settings)
+    }
+
+    fn start_file(&mut self, name: &str) -> anyhow::Result<()> {
+        self.inner.start_file(name)
     }
 }
 
-fn get_temp(s: &str) -> anyhow::Result<Option<R64>> {
-    if s.is_empty() {
-        Ok(None)
-    } else {
-        Some(s.parse::<f64>().map(r64).map_err(Into::into)).transpose()
+fn write_notes(files: &[M5File], path: &Path) -> anyhow::Result<()> {
+    let mut wtr = BufWriter::new(File::create(path).context("creating notes file")?);
+
+    for (i, note) in files.iter().flat_map(M5File::notes).enumerate() {
+        if i > 0 {
+            writeln!(wtr)?;
+        }
+        wtr.write_all(note.as_bytes())?;
     }
+
+    wtr.flush().map_err(Into::into)
 }
 
-fn get_time(s: &str) -> anyhow::Result<Option<R64>> {
-    if s.is_empty() {
-        Ok(None)
+/// Output written alongside the main output, where `-` means stderr
+fn open_sidecar(path: &Path) -> io::Result<Box<dyn Write>> {
+    if path.as_os_str() == "-" {
+        Ok(Box::new(io::stderr()))
     } else {
-        Some(parse_time(s)).transpose()
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
     }
 }
 
-fn parse_time(s: &str) -> anyhow::Result<R64> {
-    let mut it = s.splitn(2, ':');
-    let h: f64 = it
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("No hours in time: {}, s"))
-        .and_then(|h| h.parse().map_err(Into::into))?;
-    let m: f64 = it
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("No minutes in time: {}, s"))
-        .and_then(|m| m.parse().map_err(Into::into))?;
+/// Appended gzip output is a further gzip member, which decoders read on from the first.
+/// An output file is buffered by `buffer_size` bytes, or [`DEFAULT_BUFFER_SIZE`]
+fn open_output(
+    path: Option<&Path>,
+    gzip: bool,
+    append: bool,
+    buffer_size: Option<usize>,
+) -> anyhow::Result<Output> {
+    let wtr: Box<dyn Write> = match path {
+        Some(p) => {
+            let f = match append {
+                true => OpenOptions::new().create(true).append(true).open(p),
+                false => File::create(p),
+            };
+            let f = f.context("creating output file")?;
+            let capacity = buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE);
+            Box::new(BufWriter::with_capacity(capacity, f))
+        }
+        None => Box::new(io::stdout()),
+    };
 
-    Ok(r64(h + (m / 60.0)))
+    let sink = match gzip {
+        true => Sink::Gzip(GzEncoder::new(wtr, Compression::default())),
+        false => Sink::Plain(wtr),
+    };
+    Ok(Output(Rc::new(RefCell::new(sink))))
 }
 
-fn parse_settings(info: &str) -> anyhow::Result<PlateSettings> {
-    let info = info.split('\t').map(str::trim).collect::<Vec<_>>();
+/// An output stream, shared between the writer writing it and the [`FinishOutput`] that
+/// finishes it
+#[derive(Clone)]
+struct Output(Rc<RefCell<Sink>>);
+
+enum Sink {
+    Plain(Box<dyn Write>),
+    Gzip(GzEncoder<Box<dyn Write>>),
+}
 
-    // todo: probably less, check for < 12 first for initial info
-    // then check for more for specific things (F vs ABS, eg)
-    if info.len() < 31 {
-        anyhow::bail!(
-            "Less plate info entries than expected ({} < 31)",
-            info.len()
-        );
+impl Output {
+    /// Write any gzip trailer, then flush the stream under it, which is written to directly
+    /// from then on. Dropping the buffer or encoder would do the same, but without reporting
+    /// a failure
+    fn finish(&self) -> io::Result<()> {
+        let mut sink = self.0.borrow_mut();
+        let mut wtr = match std::mem::replace(&mut *sink, Sink::Plain(Box::new(io::sink()))) {
+            Sink::Plain(wtr) => wtr,
+            Sink::Gzip(gz) => gz.finish()?,
+        };
+        wtr.flush()?;
+        *sink = Sink::Plain(wtr);
+        Ok(())
     }
+}
 
-    let name = info[1].to_string();
-    let read_type = info[4].to_string();
-    let read_mode = ReadMode::from_str(info[5])?;
-    let reads = info[9].parse()?;
-    let pattern = info[10].to_string();
-
-    // sub function based on read_mode
-    let waveno: usize = info[15].parse()?;
-    let ems = info[20].split_whitespace();
-    let exs = info[16].split_whitespace();
-    let wavelengths = ems
-        .zip(exs)
-        .take(waveno)
-        .map(|(em, ex)| {
-            em.parse()
-                .and_then(|em| ex.parse().map(|ex| Wavelength::Fluorescence(em, ex)))
-        })
-        .collect::<Result<Vec<_>, _>>()?;
-
-    let wells = info[19].parse()?;
-    let rows = info[30].parse()?;
-    let cols = info[18].parse()?;
-
-    let settings = PlateSettings {
-        name,
-        rows,
-        cols,
-        wells,
-        wavelengths,
-        reads,
-        read_mode,
-        read_type,
-        pattern,
-    };
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut *self.0.borrow_mut() {
+            Sink::Plain(wtr) => wtr.write(buf),
+            Sink::Gzip(gz) => gz.write(buf),
+        }
+    }
 
-    Ok(settings)
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut *self.0.borrow_mut() {
+            Sink::Plain(wtr) => wtr.flush(),
+            Sink::Gzip(gz) => gz.flush(),
+        }
+    }
 }
-
-// TODO: platesettings {basic, read_info, plate_info}
-#[derive(Debug)]
-struct PlateSettings {
-    name: String,
-    rows: u8,
-    cols: u8,
-    wells: u32,
-    wavelengths: Vec<Wavelength>,
-    reads: u32, // timepoints, probably
-    read_mode: ReadMode,
-    read_type: String,
-    pattern: String,
-}
-
-
-
-#[derive(Debug)]
-struct WellValue<'a> {
-    plate: &'a str,
-    /// for now, only deg. Celcius
-    temp: R64,
-    /// hours
-    time: R64,
-    wavelength: Wavelength,
-    /// zero-indexed (row, col)
-    well: (u8, u8),
-    value: f64,
-}
-*/
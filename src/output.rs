@@ -1,39 +1,3131 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use noisy_float::prelude::*;
-use std::{borrow::Cow, collections::HashMap, fmt::Write as _, hash::Hash, io::Write};
-
-use crate::m5::{M5File, PlateBlock, ReadInfo, Wavelength, WellRC};
-
-pub(crate) fn write_csv(file: M5File, wtr: Box<dyn Write>) -> Result<()> {
-    const HEADER: &[&str] = &[
-        "Plate",
-        "Well",
-        "Row",
-        "Col",
-        "Time [hr]",
-        "Temperature [C]",
-        "Read Mode",
-        "Excitation [nm]",
-        "Emission [nm]",
-        "Wavelength Description",
-        "Value",
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    hash::Hash,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::error::M5Error;
+use crate::m5::{
+    plate_dimensions, CellValue, M5File, OdUnit, ParseOptions, PlateBlock, PlateSettings,
+    PolarizationChannel, ReadInfo, ReadMode, TempUnit, Wavelength, WellRC, WellValue, PATHCHECK_NM,
+};
+use crate::warning::{Warning, WarningSink};
+use crate::wells::{fmt_row, well_name, Layout, WellSelection};
+
+/// Formatting options shared by all output formats
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    pub temp_unit: TempUnit,
+    pub time_format: TimeFormat,
+    /// only output these wavelengths, or all if empty
+    pub wavelengths: Vec<Wavelength>,
+    /// only output reads at or after this time [hr]; also drops reads without a time
+    pub time_min: Option<f64>,
+    /// only output reads at or before this time [hr]
+    pub time_max: Option<f64>,
+    /// only output these wells, or all if `None`
+    pub wells: Option<WellSelection>,
+    /// only output wells whose value (after any blank correction) is at least this
+    pub value_min: Option<f64>,
+    /// only output wells whose value is at most this
+    pub value_max: Option<f64>,
+    /// with a value bound, keep overflowed wells, counting them as above any value; masked
+    /// wells are always dropped
+    pub include_overflow: bool,
+    /// adds a Sample column to CSV output, looked up by well
+    pub layout: Option<Layout>,
+    /// subtract the mean of these wells from every value of the same read and wavelength.
+    /// Reads are already passed around whole, so this doesn't add any buffering
+    pub blank: Option<WellSelection>,
+    /// divide each value by the mean of these wells of the same read and wavelength (after
+    /// `blank`), for the Normalized column of CSV, JSON, and parquet output
+    pub normalize_to: Option<WellSelection>,
+    /// positive control wells, for the QC output's Z-factor along with `blank`
+    pub positive_control: Option<WellSelection>,
+    /// adds a Source File column, filled in from [`ReadWriter::start_file`]
+    pub source_files: bool,
+    /// adds a Raw Time column, with read times as written in the export
+    pub raw_time: bool,
+    /// adds a Raw Value column, with well values as written in the export; needs
+    /// [`ParseOptions::raw_values`]
+    pub raw_value: bool,
+    /// adds an Experiment column, with the 1-based index of each plate's export in a file
+    /// of concatenated exports
+    pub experiment: bool,
+    /// adds a Block column, with the 1-based position of each plate's block in its export
+    pub block_index: bool,
+    /// adds Read Type and Read Pattern columns; the pattern is only filled in for well
+    /// scan reads
+    pub read_type: bool,
+    /// adds Point Row and Point Col columns, with the 1-based position of each value's
+    /// point in its well; well scan reads only
+    pub scan_points: bool,
+    /// adds a Value Unit column, with the unit of absorbance values
+    pub value_unit: bool,
+    /// convert absorbance values to this unit, or keep each plate's own if `None`
+    pub od_unit: Option<OdUnit>,
+    /// replace the parallel and perpendicular values of FP reads with each well's mP,
+    /// using this G factor
+    pub fp_mp: Option<f64>,
+    /// correct absorbance values to a 1 cm pathlength, for the Corrected OD column, using
+    /// this water constant [OD/cm] for the 900 and 977 (or 1000) nm reference reads
+    pub pathlength_k: Option<f64>,
+    /// decimal places of values, temperatures, and decimal-hour times in text output, or
+    /// as many as it takes to round-trip if `None`
+    pub precision: Option<usize>,
+    /// written in place of empty CSV cells, such as the time of an endpoint read or the
+    /// excitation of an absorbance read; empty by default
+    pub null_value: String,
+    /// leave out the CSV header row, e.g. when appending to a file that already has one
+    pub no_header: bool,
+    /// stop after writing this many wells; streaming output also stops parsing there
+    pub limit: Option<usize>,
+    /// replace an existing SQLite output database; without this, [`SqliteWriter`] fails
+    /// rather than delete it. Other formats always replace their output file
+    pub overwrite: bool,
+    /// bytes the CSV and matrix writers buffer before writing to their output, or
+    /// [`DEFAULT_BUFFER_SIZE`] if `None`
+    pub buffer_size: Option<usize>,
+    /// split each plate name into the Plate column (capture group 1) and a Run column
+    /// (capture group 2), for CSV and xlsx output. A name that doesn't match is kept whole,
+    /// with an empty run
+    pub plate_name_pattern: Option<Regex>,
+    /// the CSV (and xlsx) columns to write, in order, or all of them if empty
+    pub columns: Vec<Column>,
+    /// header labels for the written columns, in order, in place of their own; needs one
+    /// per column. Empty for the usual labels
+    pub header: Vec<String>,
+    /// where warnings about the output (e.g. a missing reference value) are reported
+    pub warnings: WarningSink,
+}
+
+/// How read times are written in text output
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum TimeFormat {
+    /// decimal hours, e.g. `1.5`
+    #[default]
+    Hours,
+    /// `HH:MM:SS`, with hours past 24 kept rather than wrapped (`27:15:00`)
+    Clock,
+}
+
+impl TimeFormat {
+    fn header(self) -> &'static str {
+        match self {
+            Self::Hours => "Time [hr]",
+            Self::Clock => "Time [hh:mm:ss]",
+        }
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "hours" => Ok(Self::Hours),
+            "clock" => Ok(Self::Clock),
+            _ => Err(anyhow!("Unknown time format: {}", s)),
+        }
+    }
+}
+
+/// A CSV or xlsx output column, named on the command line by its [`Column::NAMES`] entry
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Column {
+    Experiment,
+    Block,
+    SourceFile,
+    Plate,
+    Run,
+    Well,
+    Row,
+    Col,
+    PointRow,
+    PointCol,
+    Time,
+    RawTime,
+    Temperature,
+    ReadType,
+    ReadPattern,
+    ReadMode,
+    Excitation,
+    Emission,
+    Description,
+    Value,
+    RawValue,
+    ValueUnit,
+    Status,
+    Delay,
+    Integration,
+    Normalized,
+    CorrectedOd,
+    Sample,
+}
+
+impl Column {
+    /// In the full output order, which is also the order of the variants
+    pub const ALL: [Self; 28] = [
+        Self::Experiment,
+        Self::Block,
+        Self::SourceFile,
+        Self::Plate,
+        Self::Run,
+        Self::Well,
+        Self::Row,
+        Self::Col,
+        Self::PointRow,
+        Self::PointCol,
+        Self::Time,
+        Self::RawTime,
+        Self::Temperature,
+        Self::ReadType,
+        Self::ReadPattern,
+        Self::ReadMode,
+        Self::Excitation,
+        Self::Emission,
+        Self::Description,
+        Self::Value,
+        Self::RawValue,
+        Self::ValueUnit,
+        Self::Status,
+        Self::Delay,
+        Self::Integration,
+        Self::Normalized,
+        Self::CorrectedOd,
+        Self::Sample,
     ];
+    pub const NAMES: [&'static str; 28] = [
+        "experiment",
+        "block",
+        "source",
+        "plate",
+        "run",
+        "well",
+        "row",
+        "col",
+        "point_row",
+        "point_col",
+        "time",
+        "raw_time",
+        "temperature",
+        "read_type",
+        "read_pattern",
+        "mode",
+        "excitation",
+        "emission",
+        "description",
+        "value",
+        "raw_value",
+        "value_unit",
+        "status",
+        "delay",
+        "integration",
+        "normalized",
+        "corrected_od",
+        "sample",
+    ];
+
+    fn header(self, opts: &OutputOptions) -> Cow<'static, str> {
+        match self {
+            Self::Experiment => "Experiment".into(),
+            Self::Block => "Block".into(),
+            Self::SourceFile => "Source File".into(),
+            Self::Plate => "Plate".into(),
+            Self::Run => "Run".into(),
+            Self::Well => "Well".into(),
+            Self::Row => "Row".into(),
+            Self::Col => "Col".into(),
+            Self::PointRow => "Point Row".into(),
+            Self::PointCol => "Point Col".into(),
+            Self::Time => opts.time_format.header().into(),
+            Self::RawTime => "Raw Time".into(),
+            Self::Temperature => format!("Temperature [{}]", opts.temp_unit).into(),
+            Self::ReadType => "Read Type".into(),
+            Self::ReadPattern => "Read Pattern".into(),
+            Self::ReadMode => "Read Mode".into(),
+            Self::Excitation => "Excitation [nm]".into(),
+            Self::Emission => "Emission [nm]".into(),
+            Self::Description => "Wavelength Description".into(),
+            Self::Value => "Value".into(),
+            Self::RawValue => "Raw Value".into(),
+            Self::ValueUnit => "Value Unit".into(),
+            Self::Status => "Status".into(),
+            Self::Delay => "Delay [us]".into(),
+            Self::Integration => "Integration [us]".into(),
+            Self::Normalized => "Normalized".into(),
+            Self::CorrectedOd => "Corrected OD".into(),
+            Self::Sample => "Sample".into(),
+        }
+    }
+
+    /// The option that adds this column, if it isn't always written
+    fn requires(self, opts: &OutputOptions) -> Option<&'static str> {
+        match self {
+            Self::Experiment if !opts.experiment => Some("--experiment"),
+            Self::Block if !opts.block_index => Some("--block-index"),
+            Self::SourceFile if !opts.source_files => Some("several inputs"),
+            Self::Run if opts.plate_name_pattern.is_none() => Some("--split-plate-name"),
+            Self::RawTime if !opts.raw_time => Some("--raw-time"),
+            Self::RawValue if !opts.raw_value => Some("--raw-value"),
+            Self::ReadType | Self::ReadPattern if !opts.read_type => Some("--read-type"),
+            Self::PointRow | Self::PointCol if !opts.scan_points => Some("--scan-points"),
+            Self::ValueUnit if !opts.value_unit => Some("--value-unit"),
+            Self::Normalized if opts.normalize_to.is_none() => Some("--normalize-to"),
+            Self::CorrectedOd if opts.pathlength_k.is_none() => Some("--pathlength-correct"),
+            Self::Sample if opts.layout.is_none() => Some("--layout"),
+            _ => None,
+        }
+    }
+}
+
+/// The columns `opts` selects, in output order. Fails if one of `opts.columns` isn't
+/// being written
+fn selected_columns(opts: &OutputOptions) -> Result<Vec<Column>> {
+    if opts.columns.is_empty() {
+        let all = Column::ALL.iter().copied();
+        return Ok(all.filter(|c| c.requires(opts).is_none()).collect());
+    }
+
+    opts.columns
+        .iter()
+        .map(|&c| match c.requires(opts) {
+            Some(option) => Err(anyhow!(
+                "The {} column needs {}",
+                Column::NAMES[c as usize],
+                option
+            )),
+            None => Ok(c),
+        })
+        .collect()
+}
+
+/// Header labels of `columns`: `opts.header`, or the columns' own without it. Fails if
+/// `opts.header` doesn't have a label for each column
+fn column_headers<'o>(opts: &'o OutputOptions, columns: &[Column]) -> Result<Vec<Cow<'o, str>>> {
+    if opts.header.is_empty() {
+        return Ok(columns.iter().map(|c| c.header(opts)).collect());
+    }
+    if opts.header.len() != columns.len() {
+        bail!(
+            "--header gives {} column names, but {} columns are written",
+            opts.header.len(),
+            columns.len()
+        );
+    }
+
+    Ok(opts
+        .header
+        .iter()
+        .map(|h| Cow::Borrowed(h.as_str()))
+        .collect())
+}
+
+impl FromStr for Column {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::NAMES
+            .iter()
+            .position(|&name| name.eq_ignore_ascii_case(s.trim()))
+            .map(|i| Self::ALL[i])
+            .ok_or_else(|| anyhow!("Unknown column: {} (valid: {})", s, Self::NAMES.join(", ")))
+    }
+}
+
+/// The CSV writers' buffer size [bytes] without [`OutputOptions::buffer_size`]; the same
+/// as the csv crate's own default
+pub const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+impl OutputOptions {
+    /// A CSV writer builder with the buffer size of `self`
+    fn csv_builder(&self) -> csv::WriterBuilder {
+        let mut builder = csv::WriterBuilder::new();
+        builder.buffer_capacity(self.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE));
+        builder
+    }
+
+    /// The Plate and Run columns of a plate name, split by `plate_name_pattern`
+    fn plate_and_run<'s>(&self, name: &'s str) -> (&'s str, &'s str) {
+        let caps = self
+            .plate_name_pattern
+            .as_ref()
+            .and_then(|re| re.captures(name));
+        let group = |i| caps.as_ref()?.get(i).map(|m| m.as_str());
+        match group(1) {
+            Some(plate) => (plate, group(2).unwrap_or_default()),
+            None => (name, ""),
+        }
+    }
+
+    /// The wavelengths of a plate's reads once transformed, where `fp_mp` replaces each ex/em
+    /// pair's polarization channels with mP
+    fn read_wavelengths<'s>(&self, settings: &'s PlateSettings) -> Cow<'s, [Wavelength]> {
+        if self.fp_mp.is_none() || settings.read_mode != ReadMode::FluorescencePolarization {
+            return Cow::Borrowed(settings.wavelengths());
+        }
+
+        let mut wavelengths = Vec::new();
+        for &wavelength in settings.wavelengths() {
+            if let Wavelength::Polarization { ex, em, .. } = wavelength {
+                let mp = Wavelength::Polarization {
+                    ex,
+                    em,
+                    channel: PolarizationChannel::Millipolarization,
+                };
+                if !wavelengths.contains(&mp) {
+                    wavelengths.push(mp);
+                }
+            }
+        }
+        Cow::Owned(wavelengths)
+    }
+
+    /// Unit of a plate's absorbance values once converted to `od_unit`
+    fn value_unit(&self, settings: &PlateSettings) -> Option<OdUnit> {
+        settings.od_unit().map(|unit| self.od_unit.unwrap_or(unit))
+    }
+
+    fn keeps_wavelength(&self, wavelength: Wavelength) -> bool {
+        self.wavelengths.is_empty() || self.wavelengths.contains(&wavelength)
+    }
+
+    fn keeps_well(&self, well: &WellValue) -> bool {
+        self.keeps_wavelength(well.wavelength)
+            && !matches!(&self.wells, Some(sel) if !sel.contains(well.well))
+            && self.keeps_value(well.value)
+    }
+
+    /// Both bounds are inclusive
+    fn keeps_value(&self, value: CellValue) -> bool {
+        if self.value_min.is_none() && self.value_max.is_none() {
+            return true;
+        }
+        match value {
+            CellValue::Num(v) => {
+                !matches!(self.value_min, Some(min) if v < min)
+                    && !matches!(self.value_max, Some(max) if v > max)
+            }
+            CellValue::Overflow => self.include_overflow && self.value_max.is_none(),
+            CellValue::Masked | CellValue::NonFinite(_) => false,
+        }
+    }
+
+    fn keeps_read(&self, info: &ReadInfo) -> bool {
+        match info.get_time().map(R64::raw) {
+            Some(t) => {
+                !matches!(self.time_min, Some(min) if t < min)
+                    && !matches!(self.time_max, Some(max) if t > max)
+            }
+            None => self.time_min.is_none(),
+        }
+    }
+}
+
+/// Output format that is fed one plate read at a time, so a file can be converted
+/// without keeping all of its reads in memory
+pub trait ReadWriter {
+    /// Write the (already filtered) wells of one read of a plate
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()>;
+
+    /// Write any trailing output and flush; call once after the last read
+    fn finish(&mut self) -> Result<()>;
+
+    /// Called before the first read of each plate block
+    fn start_plate(&mut self, _settings: &PlateSettings) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called before the reads of each input, when several are written to one output
+    fn start_file(&mut self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Parse an export from `rdr`, writing each plate read to `wtr` as soon as it's parsed.
+///
+/// Returns the rest of the file, as [`M5File::for_each_read`] does, or no blocks if
+/// `opts.limit` stopped the parse early
+pub fn write_stream<R: BufRead>(
+    rdr: R,
+    parse_opts: &ParseOptions,
+    wtr: &mut dyn ReadWriter,
+    opts: &OutputOptions,
+) -> Result<M5File> {
+    let mut seen = Vec::new();
+    let mut left = opts.limit;
+    let file = stream_reads(rdr, parse_opts, wtr, opts, &mut seen, &mut left)?;
+
+    if file.is_some() {
+        warn_missing_wavelengths(opts, |wavelength| seen.contains(&wavelength));
+    }
+    wtr.finish()?;
+
+    Ok(file.unwrap_or_default())
+}
+
+/// [`write_stream`] for several (name, export) inputs in turn, all written to `wtr`.
+///
+/// Each input's name is passed to [`ReadWriter::start_file`] first, and is added to the
+/// error if the input fails to parse. An input that fails to open stops the conversion, and
+/// reaching `opts.limit` stops it early, without opening any more inputs
+pub fn write_streams<I, R>(
+    inputs: I,
+    parse_opts: &ParseOptions,
+    wtr: &mut dyn ReadWriter,
+    opts: &OutputOptions,
+) -> Result<Vec<M5File>>
+where
+    I: IntoIterator<Item = Result<(String, R)>>,
+    R: BufRead,
+{
+    let mut seen = Vec::new();
+    let mut files = Vec::new();
+    let mut left = opts.limit;
+
+    for input in inputs {
+        if left == Some(0) {
+            break;
+        }
+        let (name, rdr) = input?;
+        wtr.start_file(&name)?;
+        let file = stream_reads(rdr, parse_opts, wtr, opts, &mut seen, &mut left)
+            .with_context(|| anyhow!("converting {}", name))?;
+        match file {
+            Some(file) => files.push(file),
+            None => break,
+        }
+    }
+
+    // the inputs weren't all read, so some wavelengths may just not have been reached
+    if left != Some(0) {
+        warn_missing_wavelengths(opts, |wavelength| seen.contains(&wavelength));
+    }
+    wtr.finish()?;
+
+    Ok(files)
+}
+
+/// Convert one export to CSV, streaming each read from `rdr` to `wtr` as it's parsed. Returns
+/// the rest of the file, as [`write_stream`] does
+pub fn convert<R, W>(
+    rdr: R,
+    wtr: W,
+    parse_opts: &ParseOptions,
+    opts: &OutputOptions,
+) -> Result<M5File>
+where
+    R: BufRead,
+    W: Write + 'static,
+{
+    let mut wtr = CsvWriter::new(Box::new(wtr), b',', opts)?;
+    write_stream(rdr, parse_opts, &mut wtr, opts).context("writing CSV data")
+}
+
+/// Write the reads of one export, without finishing `wtr`; `seen` collects the
+/// wavelengths of its plates. `left` is how many more wells can be written, if limited;
+/// running out stops the parse, returning `None`, and none left skips it
+fn stream_reads<R: BufRead>(
+    rdr: R,
+    parse_opts: &ParseOptions,
+    wtr: &mut dyn ReadWriter,
+    opts: &OutputOptions,
+    seen: &mut Vec<Wavelength>,
+    left: &mut Option<usize>,
+) -> Result<Option<M5File>> {
+    if *left == Some(0) {
+        return Ok(None);
+    }
+    // reads left in the current block; each block passes exactly `reads()` reads
+    let mut remaining = 0;
+
+    let file = M5File::for_each_read(rdr, parse_opts, |settings, read_info, mut wells| {
+        if remaining == 0 {
+            wtr.start_plate(settings)?;
+            remaining = settings.reads();
+        }
+        remaining -= 1;
+
+        for &wavelength in opts.read_wavelengths(settings).iter() {
+            if !seen.contains(&wavelength) {
+                seen.push(wavelength);
+            }
+        }
+        if !opts.keeps_read(&read_info) {
+            return Ok(());
+        }
+        transform_read(opts, settings, &mut wells);
+        wells.retain(|w| opts.keeps_well(w));
+        let done = take_limit(left, &mut wells);
+        wtr.write_read(settings, &read_info, &wells)?;
+        if done {
+            bail!(LimitReached);
+        }
+        Ok(())
+    });
+
+    match file {
+        Ok(file) => Ok(Some(file)),
+        Err(M5Error::Other(e)) if e.downcast_ref::<LimitReached>().is_some() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Stops a streaming parse once `OutputOptions::limit` wells have been written
+#[derive(Debug)]
+struct LimitReached;
+
+impl std::fmt::Display for LimitReached {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("output limit reached")
+    }
+}
+
+impl std::error::Error for LimitReached {}
+
+/// Cut `wells` down to the `left` that can still be written, counting them off. Returns
+/// if the limit has been reached
+fn take_limit(left: &mut Option<usize>, wells: &mut Vec<WellValue>) -> bool {
+    match left {
+        Some(left) => {
+            wells.truncate(*left);
+            *left -= wells.len();
+            *left == 0
+        }
+        None => false,
+    }
+}
+
+/// Write all plates of an already parsed `file`
+fn write_file(file: M5File, wtr: &mut dyn ReadWriter, opts: &OutputOptions) -> Result<()> {
+    let mut left = opts.limit;
+
+    'plates: for mut block in plates(file, opts) {
+        if left == Some(0) {
+            break;
+        }
+        wtr.start_plate(&block.settings)?;
+        for (read_info, wells) in &mut block.data {
+            let done = take_limit(&mut left, wells);
+            wtr.write_read(&block.settings, read_info, wells)?;
+            if done {
+                break 'plates;
+            }
+        }
+    }
+
+    wtr.finish()
+}
+
+/// Plate blocks of `file`, with any wells not selected by the output options removed
+fn plates(file: M5File, opts: &OutputOptions) -> impl Iterator<Item = PlateBlock> + '_ {
+    warn_missing_wavelengths(opts, |wavelength| {
+        file.plates()
+            .any(|p| opts.read_wavelengths(&p.settings).contains(&wavelength))
+    });
+
+    file.into_plates().map(move |mut block| {
+        block.data.retain(|(info, _)| opts.keeps_read(info));
+        for (_, wells) in &mut block.data {
+            transform_read(opts, &block.settings, wells);
+            wells.retain(|w| opts.keeps_well(w));
+        }
+        block
+    })
+}
+
+/// Per-read corrections, applied before the wells are filtered so the blank and reference
+/// wells don't need to be output. All work on a whole read at a time, which streaming
+/// already keeps together, so none holds on to more than one read
+fn transform_read(opts: &OutputOptions, settings: &PlateSettings, wells: &mut Vec<WellValue>) {
+    if let (Some(from), Some(to)) = (settings.od_unit(), opts.od_unit) {
+        if from != to {
+            for well in wells.iter_mut() {
+                if let CellValue::Num(v) = &mut well.value {
+                    *v = from.convert(*v, to);
+                }
+            }
+        }
+    }
+    // from the raw reference values, which subtracting a (water) blank would zero out. The
+    // water constant is in OD/cm, so it's scaled to the unit of the values
+    let pathlengths = opts.pathlength_k.and_then(|k| {
+        let unit = opts.value_unit(settings)?;
+        Some(pathlengths(k * unit.per_od(), settings, wells))
+    });
+    if let Some(blank) = &opts.blank {
+        subtract_blank(
+            blank,
+            settings,
+            settings.wavelengths(),
+            wells,
+            &opts.warnings,
+        );
+    }
+    if let Some(g) = opts.fp_mp {
+        millipolarization(g, settings, wells, &opts.warnings);
+    }
+    if let Some(reference) = &opts.normalize_to {
+        normalize(
+            reference,
+            settings,
+            &opts.read_wavelengths(settings),
+            wells,
+            &opts.warnings,
+        );
+    }
+    if let Some(pathlengths) = pathlengths {
+        for well in wells.iter_mut() {
+            let sample = match well.wavelength {
+                Wavelength::Absorbance(nm) => !PATHCHECK_NM.contains(&nm),
+                _ => false,
+            };
+            well.corrected = pathlengths
+                .get(&well.well)
+                .filter(|_| sample)
+                .and_then(|pathlength| Some(well.value.num()? / pathlength));
+        }
+    }
+}
+
+/// Each well's pathlength [cm] in an absorbance read, from its reference reads:
+/// `(A977 - A900) / k`, where `k` is the water constant, the absorbance of water over a
+/// 1 cm path in the unit of the values (with A1000 standing in for a missing A977). A
+/// value corrected to 1 cm is then `A / pathlength`.
+///
+/// Wells missing a numeric reference value, or with a pathlength that isn't positive, are
+/// left out
+fn pathlengths(k: f64, settings: &PlateSettings, wells: &[WellValue]) -> HashMap<WellRC, f64> {
+    let [background, ..] = PATHCHECK_NM;
+    // read as plain absorbance wavelengths, or as an M5e PathCheck read
+    let reference = |nm| [Wavelength::Absorbance(nm), Wavelength::PathCheck(nm)];
+    let sample = PATHCHECK_NM[1..].iter().copied().find(|&nm| {
+        reference(nm)
+            .iter()
+            .any(|w| settings.wavelengths().contains(w))
+    });
+    let sample = match sample {
+        Some(nm) if settings.read_mode == ReadMode::Absorbance => nm,
+        _ => return HashMap::new(),
+    };
+
+    // well -> (background, sample) values
+    let mut refs = HashMap::<WellRC, [Option<f64>; 2]>::new();
+    for well in wells {
+        let i = match well.wavelength {
+            Wavelength::Absorbance(nm) | Wavelength::PathCheck(nm) if nm == background => 0,
+            Wavelength::Absorbance(nm) | Wavelength::PathCheck(nm) if nm == sample => 1,
+            _ => continue,
+        };
+        refs.entry(well.well).or_default()[i] = well.value.num();
+    }
+
+    refs.into_iter()
+        .filter_map(|(well, [bg, sample])| {
+            let pathlength = (sample? - bg?) / k;
+            Some((well, pathlength)).filter(|_| pathlength > 0.0 && pathlength.is_finite())
+        })
+        .collect()
+}
+
+/// Replace the parallel (P) and perpendicular (S) values of an FP read with each well's
+/// `1000 * (P - g*S) / (P + g*S)`.
+///
+/// A well without numeric values for both channels is dropped, with a warning for the
+/// read, unless both are masked
+fn millipolarization(
+    g: f64,
+    settings: &PlateSettings,
+    wells: &mut Vec<WellValue>,
+    warnings: &WarningSink,
+) {
+    if settings.read_mode != ReadMode::FluorescencePolarization {
+        return;
+    }
+
+    // (well, ex, em) -> index of its (P, S) values, kept in the order the wells were read
+    let mut index = HashMap::new();
+    let mut channels = Vec::<((WellRC, u16, u16), [Option<CellValue>; 2])>::new();
+    for well in wells.iter() {
+        if let Wavelength::Polarization { ex, em, channel } = well.wavelength {
+            let key = (well.well, ex, em);
+            let i = *index.entry(key).or_insert_with(|| {
+                channels.push((key, [None, None]));
+                channels.len() - 1
+            });
+            match channel {
+                PolarizationChannel::Parallel => channels[i].1[0] = Some(well.value),
+                PolarizationChannel::Perpendicular => channels[i].1[1] = Some(well.value),
+                PolarizationChannel::Millipolarization => (),
+            }
+        }
+    }
+
+    let mut skipped = 0;
+    *wells = channels
+        .into_iter()
+        .filter_map(|((well, ex, em), [p, s])| {
+            let value = match (p, s) {
+                (Some(CellValue::Num(p)), Some(CellValue::Num(s))) => {
+                    let mp = 1000.0 * (p - g * s) / (p + g * s);
+                    Some(CellValue::Num(mp)).filter(|_| mp.is_finite())
+                }
+                (Some(CellValue::Masked), Some(CellValue::Masked)) => Some(CellValue::Masked),
+                _ => None,
+            };
+            if value.is_none() {
+                skipped += 1;
+            }
+
+            Some(WellValue {
+                wavelength: Wavelength::Polarization {
+                    ex,
+                    em,
+                    channel: PolarizationChannel::Millipolarization,
+                },
+                well,
+                value: value?,
+                normalized: None,
+                corrected: None,
+                point: None,
+                raw: None,
+            })
+        })
+        .collect();
+
+    if skipped > 0 {
+        warnings.warn(Warning::SkippedMp {
+            plate: settings.name.clone(),
+            count: skipped,
+        });
+    }
+}
+
+/// Subtract the mean of the `blank` wells from each value of one read, per wavelength.
+///
+/// A wavelength without any numeric blank wells is left uncorrected
+fn subtract_blank(
+    blank: &WellSelection,
+    settings: &PlateSettings,
+    wavelengths: &[Wavelength],
+    wells: &mut [WellValue],
+    warnings: &WarningSink,
+) {
+    let means = selection_means(blank, "blank", settings, wavelengths, wells, warnings);
+
+    for well in wells {
+        if let (CellValue::Num(v), Some(mean)) = (&mut well.value, means.get(&well.wavelength)) {
+            *v -= mean;
+        }
+    }
+}
+
+/// Set each well's normalized value: its value divided by the mean of the `reference`
+/// wells of the same wavelength, after any blank subtraction.
+///
+/// The normalized value is left empty where the reference is missing or zero
+fn normalize(
+    reference: &WellSelection,
+    settings: &PlateSettings,
+    wavelengths: &[Wavelength],
+    wells: &mut [WellValue],
+    warnings: &WarningSink,
+) {
+    let mut means = selection_means(
+        reference,
+        "reference",
+        settings,
+        wavelengths,
+        wells,
+        warnings,
+    );
+    means.retain(|wavelength, mean| {
+        if *mean == 0.0 {
+            warnings.warn(Warning::ZeroReference {
+                plate: settings.name.clone(),
+                wavelength: WaveStrings::from(*wavelength).desc,
+            });
+        }
+        *mean != 0.0
+    });
+
+    for well in wells {
+        well.normalized = means
+            .get(&well.wavelength)
+            .and_then(|mean| Some(well.value.num()? / mean));
+    }
+}
+
+/// Mean of the numeric values of the `sel` wells of one read, per wavelength.
+///
+/// Overflowed or masked wells are left out of the mean, and a wavelength without any
+/// numeric wells has no mean; both with a warning naming the wells' `role`. `wavelengths`
+/// are the read's, which each should have a mean
+fn selection_means(
+    sel: &WellSelection,
+    role: &str,
+    settings: &PlateSettings,
+    wavelengths: &[Wavelength],
+    wells: &[WellValue],
+    warnings: &WarningSink,
+) -> HashMap<Wavelength, f64> {
+    // (sum, count) of the values
+    let mut sums = HashMap::new();
+
+    for well in wells.iter().filter(|w| sel.contains(w.well)) {
+        match well.value {
+            CellValue::Num(v) => {
+                let (sum, n) = sums.entry(well.wavelength).or_insert((0.0, 0));
+                *sum += v;
+                *n += 1;
+            }
+            cell => warnings.warn(Warning::ExcludedWell {
+                plate: settings.name.clone(),
+                well: WellStrings::from(well.well).name,
+                role: role.to_string(),
+                status: cell.status().to_lowercase(),
+            }),
+        }
+    }
+
+    for wavelength in wavelengths {
+        if !sums.contains_key(wavelength) {
+            warnings.warn(Warning::NoSelectionValues {
+                plate: settings.name.clone(),
+                wavelength: WaveStrings::from(*wavelength).desc,
+                role: role.to_string(),
+            });
+        }
+    }
+
+    sums.into_iter()
+        .map(|(wavelength, (sum, n))| (wavelength, sum / n as f64))
+        .collect()
+}
+
+fn warn_missing_wavelengths<F>(opts: &OutputOptions, found: F)
+where
+    F: Fn(Wavelength) -> bool,
+{
+    for &wavelength in &opts.wavelengths {
+        if !found(wavelength) {
+            opts.warnings.warn(Warning::MissingWavelength {
+                wavelength: wavelength.to_string(),
+            });
+        }
+    }
+}
+
+/// Flat output with one row per well value; `delimiter` is `b','` for CSV or `b'\t'` for TSV
+pub fn write_csv(
+    file: M5File,
+    wtr: Box<dyn Write>,
+    delimiter: u8,
+    opts: &OutputOptions,
+) -> Result<()> {
+    let mut wtr = CsvWriter::new(wtr, delimiter, opts)?;
+    write_file(file, &mut wtr, opts).context("writing CSV data")
+}
+
+pub fn write_json(file: M5File, wtr: Box<dyn Write>, opts: &OutputOptions) -> Result<()> {
+    let mut wtr = JsonWriter::array(wtr, opts)?;
+    write_file(file, &mut wtr, opts)
+}
+
+/// Newline-delimited JSON: one compact object per well, written as the file is walked
+pub fn write_ndjson(file: M5File, wtr: Box<dyn Write>, opts: &OutputOptions) -> Result<()> {
+    let mut wtr = JsonWriter::lines(wtr, opts);
+    write_file(file, &mut wtr, opts)
+}
+
+/// The whole parsed file as a single MessagePack value, as it would deserialize into an
+/// [`M5File`]. Structs are written as maps, so other MessagePack readers see field names
+#[cfg(feature = "msgpack")]
+pub fn write_msgpack(file: &M5File, mut wtr: Box<dyn Write>) -> Result<()> {
+    rmp_serde::encode::write_named(&mut wtr, file).context("writing output file")?;
+    wtr.flush().context("flushing output")
+}
+
+/// One MessagePack map per well, with the same fields as the JSON output, written one
+/// after another as the file is walked
+#[cfg(feature = "msgpack")]
+pub fn write_msgpack_stream(file: M5File, wtr: Box<dyn Write>, opts: &OutputOptions) -> Result<()> {
+    let mut wtr = MsgpackWriter::records(wtr, opts);
+    write_file(file, &mut wtr, opts)
+}
+
+/// Classic plate layout: one labeled grid per (plate, read, wavelength), with unread
+/// wells left blank
+pub fn write_matrix(file: M5File, wtr: Box<dyn Write>, opts: &OutputOptions) -> Result<()> {
+    let mut wtr = MatrixWriter::new(wtr, opts);
+    write_file(file, &mut wtr, opts)
+}
+
+/// Columnar output; needs a real file since the parquet footer is written on close
+#[cfg(feature = "parquet")]
+pub fn write_parquet(file: M5File, path: &std::path::Path, opts: &OutputOptions) -> Result<()> {
+    let mut wtr = ParquetWriter::new(path, opts)?;
+    write_file(file, &mut wtr, opts)
+}
+
+/// Spreadsheet output, one sheet per plate; needs a real file since the workbook is only
+/// written on finish
+#[cfg(feature = "xlsx")]
+pub fn write_xlsx(file: M5File, path: &Path, opts: &OutputOptions) -> Result<()> {
+    let mut wtr = XlsxWriter::new(path, opts)?;
+    write_file(file, &mut wtr, opts)
+}
+
+/// A new SQLite database with a `readings` table of every well; needs a real file, which
+/// mustn't exist yet unless `opts.overwrite`
+#[cfg(feature = "sqlite")]
+pub fn write_sqlite(file: M5File, path: &Path, opts: &OutputOptions) -> Result<()> {
+    let mut wtr = SqliteWriter::new(path, opts)?;
+    write_file(file, &mut wtr, opts)
+}
+
+pub struct CsvWriter<'a> {
+    wtr: csv::Writer<Box<dyn Write>>,
+    cache: Cache,
+    value: String,
+    normalized: String,
+    corrected: String,
+    /// indices into each row of every [`Column`], in output order
+    columns: Vec<usize>,
+    opts: &'a OutputOptions,
+}
+
+impl<'a> CsvWriter<'a> {
+    /// Writes the header row right away, unless `opts.no_header`. Fails if `opts.columns`
+    /// asks for a column that isn't being written, or `opts.header` doesn't label each
+    /// column
+    pub fn new(wtr: Box<dyn Write>, delimiter: u8, opts: &'a OutputOptions) -> Result<Self> {
+        let selected = selected_columns(opts)?;
+        let header = column_headers(opts, &selected)?;
+        let columns = selected.into_iter().map(|c| c as usize).collect::<Vec<_>>();
+
+        let mut wtr = opts.csv_builder().delimiter(delimiter).from_writer(wtr);
+
+        if !opts.no_header {
+            wtr.write_record(header.iter().map(|h| h.as_ref()))
+                .context("writing output CSV header")?;
+        }
+
+        Ok(Self {
+            wtr,
+            cache: Cache::new(),
+            value: String::with_capacity(64),
+            normalized: String::with_capacity(64),
+            corrected: String::with_capacity(64),
+            columns,
+            opts,
+        })
+    }
+}
+
+impl ReadWriter for CsvWriter<'_> {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        let Self {
+            wtr,
+            cache,
+            value,
+            normalized,
+            corrected,
+            columns,
+            opts,
+        } = self;
+
+        // TRF timing is per plate, so only format it once per read
+        let fmt_us = |us: Option<u32>| us.map(|us| us.to_string()).unwrap_or_default();
+        let delay = fmt_us(settings.delay_us());
+        let integration = fmt_us(settings.integration_us());
+        let experiment = settings.experiment().to_string();
+        let block = settings.block_index().to_string();
+        let (plate, run) = opts.plate_and_run(&settings.name);
+        let unit = opts
+            .value_unit(settings)
+            .map(OdUnit::name)
+            .unwrap_or_default();
+
+        for well in wells {
+            let WellStrings { name, row, col } =
+                get_from(&mut cache.wellname, well.well, WellStrings::from);
+            let time = get_read_time(read_info, &mut cache.time, opts);
+            let temp = get_from(&mut cache.temp, read_info.temp, |t| {
+                fmt_num(opts.temp_unit.convert(t).raw(), opts.precision)
+            });
+            let WaveStrings { ex, em, desc } =
+                get_from(&mut cache.wl, well.wavelength, WaveStrings::from);
+            let (point_row, point_col) = well
+                .point
+                .map(|(r, c)| ((r + 1).to_string(), (c + 1).to_string()))
+                .unwrap_or_default();
+
+            if let CellValue::Num(v) = well.value {
+                write_num(value, v, opts.precision)?;
+            }
+            if let Some(v) = well.normalized {
+                write_num(normalized, v, opts.precision)?;
+            }
+            if let Some(v) = well.corrected {
+                write_num(corrected, v, opts.precision)?;
+            }
+            let sample = opts
+                .layout
+                .as_ref()
+                .map(|l| l.sample(well.well))
+                .unwrap_or_default();
+
+            // in `Column` order; columns that aren't written are never picked out
+            let mode = read_mode_name(settings, well.wavelength);
+            let row: [&str; 28] = [
+                &experiment,
+                &block,
+                &cache.source,
+                plate,
+                run,
+                name,
+                row,
+                col,
+                &point_row,
+                &point_col,
+                time,
+                read_info.raw_time().unwrap_or_default(),
+                temp,
+                settings.read_type.name(),
+                settings.read_pattern.as_deref().unwrap_or_default(),
+                mode,
+                ex,
+                em,
+                desc,
+                value,
+                well.raw.as_deref().unwrap_or_default(),
+                unit,
+                well.value.status(),
+                &delay,
+                &integration,
+                normalized,
+                corrected,
+                sample,
+            ];
+            let cell = |i: usize| match row[i] {
+                "" => opts.null_value.as_str(),
+                cell => cell,
+            };
+            wtr.write_record(columns.iter().map(|&i| cell(i)))
+                .context("writing output row")?;
+
+            value.clear();
+            normalized.clear();
+            corrected.clear();
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.wtr.flush().context("flushing output")
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.cache.source = name.to_string();
+        Ok(())
+    }
+}
+
+/// JSON output, either as one array of records or as newline-delimited records
+pub struct JsonWriter<'a> {
+    wtr: Box<dyn Write>,
+    cache: Cache,
+    opts: &'a OutputOptions,
+    /// `None` for newline-delimited output, otherwise if no record has been written yet
+    first: Option<bool>,
+}
+
+impl<'a> JsonWriter<'a> {
+    /// A single JSON array; the opening bracket is written right away
+    pub fn array(mut wtr: Box<dyn Write>, opts: &'a OutputOptions) -> Result<Self> {
+        wtr.write_all(b"[")?;
+
+        Ok(Self {
+            wtr,
+            cache: Cache::new(),
+            opts,
+            first: Some(true),
+        })
+    }
+
+    /// Newline-delimited JSON: one compact object per line
+    pub fn lines(wtr: Box<dyn Write>, opts: &'a OutputOptions) -> Self {
+        Self {
+            wtr,
+            cache: Cache::new(),
+            opts,
+            first: None,
+        }
+    }
+}
+
+impl ReadWriter for JsonWriter<'_> {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        let Self {
+            wtr,
+            cache,
+            opts,
+            first,
+        } = self;
+
+        for_each_record(settings, read_info, wells, cache, opts, |record| {
+            match first {
+                Some(true) => *first = Some(false),
+                Some(false) => wtr.write_all(b",")?,
+                None => (),
+            }
+            serde_json::to_writer(&mut *wtr, record).context("writing output record")?;
+            if first.is_none() {
+                wtr.write_all(b"\n")?;
+            }
+            Ok(())
+        })
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.first.is_some() {
+            self.wtr.write_all(b"]\n")?;
+        }
+        self.wtr.flush().context("flushing output")
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.cache.source = name.to_string();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "msgpack")]
+pub struct MsgpackWriter<'a> {
+    wtr: Box<dyn Write>,
+    cache: Cache,
+    opts: &'a OutputOptions,
+    /// plate blocks written so far, or `None` when each record is written as it comes
+    blocks: Option<Vec<crate::m5::Block>>,
+}
+
+#[cfg(feature = "msgpack")]
+impl<'a> MsgpackWriter<'a> {
+    /// A single [`M5File`] holding the plate blocks (of every input), written on finish.
+    /// Reads have the output options applied, and group and note blocks are left out
+    pub fn file(wtr: Box<dyn Write>, opts: &'a OutputOptions) -> Self {
+        Self {
+            wtr,
+            cache: Cache::new(),
+            opts,
+            blocks: Some(Vec::new()),
+        }
+    }
+
+    /// One map per well, with the same fields as the JSON output
+    pub fn records(wtr: Box<dyn Write>, opts: &'a OutputOptions) -> Self {
+        Self {
+            wtr,
+            cache: Cache::new(),
+            opts,
+            blocks: None,
+        }
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl ReadWriter for MsgpackWriter<'_> {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        let Self {
+            wtr,
+            cache,
+            opts,
+            blocks,
+        } = self;
+
+        match blocks {
+            Some(blocks) => match blocks.last_mut() {
+                Some(crate::m5::Block::Plate(plate)) => {
+                    plate.data.push((read_info.clone(), wells.to_vec()));
+                    Ok(())
+                }
+                _ => bail!("read written before its plate was started"),
+            },
+            None => for_each_record(settings, read_info, wells, cache, opts, |record| {
+                rmp_serde::encode::write_named(&mut *wtr, record).context("writing output record")
+            }),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(blocks) = self.blocks.take() {
+            rmp_serde::encode::write_named(
+                &mut self.wtr,
+                &M5File {
+                    blocks,
+                    metadata: Vec::new(),
+                },
+            )
+            .context("writing output file")?;
+        }
+        self.wtr.flush().context("flushing output")
+    }
+
+    fn start_plate(&mut self, settings: &PlateSettings) -> Result<()> {
+        if let Some(blocks) = &mut self.blocks {
+            blocks.push(crate::m5::Block::Plate(PlateBlock {
+                settings: settings.clone(),
+                data: Vec::new(),
+                sections: Vec::new(),
+            }));
+        }
+        Ok(())
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.cache.source = name.to_string();
+        Ok(())
+    }
+}
+
+pub struct MatrixWriter<'a> {
+    wtr: csv::Writer<Box<dyn Write>>,
+    cache: Cache,
+    value: String,
+    grid: Vec<Option<CellValue>>,
+    opts: &'a OutputOptions,
+}
+
+impl<'a> MatrixWriter<'a> {
+    pub fn new(wtr: Box<dyn Write>, opts: &'a OutputOptions) -> Self {
+        Self {
+            wtr: opts.csv_builder().flexible(true).from_writer(wtr),
+            cache: Cache::new(),
+            value: String::with_capacity(64),
+            grid: Vec::new(),
+            opts,
+        }
+    }
+}
+
+impl ReadWriter for MatrixWriter<'_> {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        let Self {
+            wtr,
+            cache,
+            value,
+            grid,
+            opts,
+        } = self;
+
+        // a cell per well has no room for the points of each well
+        if let Some(n) = settings.scan_points().filter(|&n| n > 1) {
+            bail!(
+                "Matrix output can't hold the {} x {} points per well of well scan plate {}; use csv, tsv, well-wide, or kinetic-wide output",
+                n,
+                n,
+                settings.name
+            );
+        }
+        let (rows, cols) = plate_dimensions(settings.plate_size())?;
+        let (rows, cols) = (rows as usize, cols as usize);
+        let col_labels = std::iter::once(String::new())
+            .chain((1..=cols).map(|c| c.to_string()))
+            .collect::<Vec<_>>();
+
+        let time = get_read_time(read_info, &mut cache.time, opts);
+        let temp = get_from(&mut cache.temp, read_info.temp, |t| {
+            fmt_num(opts.temp_unit.convert(t).raw(), opts.precision)
+        });
+        let temp_header = format!("Temperature [{}]", opts.temp_unit);
+
+        for &wavelength in opts.read_wavelengths(settings).iter() {
+            if !opts.keeps_wavelength(wavelength) {
+                continue;
+            }
+            grid.clear();
+            grid.resize(rows * cols, None);
+            for well in wells.iter().filter(|w| w.wavelength == wavelength) {
+                let (r, c) = (well.well.0 as usize, well.well.1 as usize);
+                if r < rows && c < cols {
+                    grid[r * cols + c] = Some(well.value);
+                }
+            }
+
+            let desc = &get_from(&mut cache.wl, wavelength, WaveStrings::from).desc;
+            let group = [
+                "Plate",
+                &settings.name,
+                opts.time_format.header(),
+                time,
+                &temp_header,
+                temp,
+                "Wavelength",
+                desc,
+            ];
+            let source = ["Source File", &cache.source];
+            let source = source.iter().filter(|_| opts.source_files);
+            wtr.write_record(group.iter().chain(source))
+                .context("writing grid header")?;
+            wtr.write_record(&col_labels)
+                .context("writing grid column labels")?;
+
+            for (r, row) in grid.chunks(cols).enumerate() {
+                wtr.write_field(fmt_row(r as u8))?;
+                for v in row {
+                    match v {
+                        Some(CellValue::Num(v)) => write_num(value, *v, opts.precision)?,
+                        Some(cell) => value.push_str(cell.status()),
+                        None => (),
+                    }
+                    wtr.write_field(&value)?;
+                    value.clear();
+                }
+                wtr.write_record(None::<&[u8]>)
+                    .context("writing grid row")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.wtr.flush().context("flushing output")
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.cache.source = name.to_string();
+        Ok(())
+    }
+}
+
+/// Kinetic reads pivoted wide: one row per well and wavelength of each plate, with a
+/// column per read time.
+///
+/// A plate's times are only known after its last read, so its wells are held until the
+/// next plate starts. Each plate gets its own header row of times, and a well missing
+/// from a read gets an empty cell. A well scan of several points per well gets a row per
+/// point, with Point Row and Point Col columns
+pub struct KineticWideWriter<'a> {
+    wtr: csv::Writer<Box<dyn Write>>,
+    cache: Cache,
+    value: String,
+    /// the current plate's name, source file as of its start, and whether it has several
+    /// points per well
+    plate: Option<(String, String, bool)>,
+    /// each well of the current plate, with its read time
+    wells: Vec<(Option<R64>, WellValue)>,
+    opts: &'a OutputOptions,
+}
+
+impl<'a> KineticWideWriter<'a> {
+    pub fn new(wtr: Box<dyn Write>, opts: &'a OutputOptions) -> Self {
+        Self {
+            wtr: opts.csv_builder().flexible(true).from_writer(wtr),
+            cache: Cache::new(),
+            value: String::with_capacity(64),
+            plate: None,
+            wells: Vec::new(),
+            opts,
+        }
+    }
+
+    /// Write out the held plate, if any
+    fn write_plate(&mut self) -> Result<()> {
+        let Self {
+            wtr,
+            cache,
+            value,
+            plate,
+            wells,
+            opts,
+        } = self;
+        let (name, source, points) = match plate.take() {
+            Some(plate) => plate,
+            None => return Ok(()),
+        };
+
+        let mut times = wells.iter().map(|&(t, _)| t).collect::<Vec<_>>();
+        times.sort_unstable();
+        times.dedup();
+
+        let mut header = vec!["Plate".to_string()];
+        if opts.source_files {
+            header.push("Source File".to_string());
+        }
+        header.push("Well".to_string());
+        if points {
+            header.push(Column::PointRow.header(opts).into_owned());
+            header.push(Column::PointCol.header(opts).into_owned());
+        }
+        header.push("Wavelength Description".to_string());
+        header.extend(times.iter().map(|t| match t {
+            Some(t) => fmt_time(*t, opts.time_format, opts.precision),
+            None => String::new(),
+        }));
+        wtr.write_record(&header)
+            .context("writing kinetic header")?;
+
+        wells.sort_by_key(|(t, w)| (w.wavelength, w.well, w.point, *t));
+        let key = |w: &WellValue| (w.wavelength, w.well, w.point);
+        let mut cells = vec![None; times.len()];
+        for group in wells.chunk_by(|(_, a), (_, b)| key(a) == key(b)) {
+            let well = &group[0].1;
+            cells.iter_mut().for_each(|c| *c = None);
+            for (t, w) in group {
+                if let Ok(i) = times.binary_search(t) {
+                    cells[i] = Some(w.value);
+                }
+            }
+
+            wtr.write_field(&name)?;
+            if opts.source_files {
+                wtr.write_field(&source)?;
+            }
+            wtr.write_field(&get_from(&mut cache.wellname, well.well, WellStrings::from).name)?;
+            if points {
+                let (r, c) = well.point.unwrap_or_default();
+                wtr.write_field((r + 1).to_string())?;
+                wtr.write_field((c + 1).to_string())?;
+            }
+            wtr.write_field(&get_from(&mut cache.wl, well.wavelength, WaveStrings::from).desc)?;
+            for cell in &cells {
+                match cell {
+                    Some(CellValue::Num(v)) => write_num(value, *v, opts.precision)?,
+                    Some(cell) => value.push_str(cell.status()),
+                    None => (),
+                }
+                wtr.write_field(&value)?;
+                value.clear();
+            }
+            wtr.write_record(None::<&[u8]>)
+                .context("writing kinetic row")?;
+        }
+        wells.clear();
+
+        Ok(())
+    }
+}
+
+impl ReadWriter for KineticWideWriter<'_> {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        if self.plate.is_none() {
+            let points = settings.scan_points().is_some_and(|n| n > 1);
+            self.plate = Some((settings.name.clone(), self.cache.source.clone(), points));
+        }
+        let time = read_info.get_time();
+        self.wells
+            .extend(wells.iter().map(|well| (time, well.clone())));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.write_plate()?;
+        self.wtr.flush().context("flushing output")
+    }
+
+    fn start_plate(&mut self, _settings: &PlateSettings) -> Result<()> {
+        self.write_plate()
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.write_plate()?;
+        self.cache.source = name.to_string();
+        Ok(())
+    }
+}
+
+/// Wavelengths pivoted wide: one row per well of each plate read, with a value column per
+/// wavelength of the plate, headed by its description. Each plate gets a header row of its
+/// own, as plates can have different wavelengths, and a well missing a wavelength has an
+/// empty cell for it.
+///
+/// A well scan of several points per well gets a row per point, with Point Row and Point
+/// Col columns. A well listed twice for a wavelength (which parsing warns about) keeps its
+/// first value
+pub struct WellWideWriter<'a> {
+    wtr: csv::Writer<Box<dyn Write>>,
+    cache: Cache,
+    /// the current plate's wavelengths, in column order
+    wavelengths: Vec<Wavelength>,
+    /// the current plate has several points per well
+    points: bool,
+    /// a row's value for each wavelength
+    cells: Vec<String>,
+    opts: &'a OutputOptions,
+}
+
+impl<'a> WellWideWriter<'a> {
+    pub fn new(wtr: Box<dyn Write>, opts: &'a OutputOptions) -> Self {
+        Self {
+            wtr: opts.csv_builder().flexible(true).from_writer(wtr),
+            cache: Cache::new(),
+            wavelengths: Vec::new(),
+            points: false,
+            cells: Vec::new(),
+            opts,
+        }
+    }
+}
+
+impl ReadWriter for WellWideWriter<'_> {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        let Self {
+            wtr,
+            cache,
+            wavelengths,
+            points,
+            cells,
+            opts,
+        } = self;
+
+        let time = get_read_time(read_info, &mut cache.time, opts).to_string();
+        let temp = get_from(&mut cache.temp, read_info.temp, |t| {
+            fmt_num(opts.temp_unit.convert(t).raw(), opts.precision)
+        })
+        .clone();
+
+        // a stable sort keeps each well's values in the order they were read
+        let mut order = (0..wells.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| (wells[i].well, wells[i].point));
+        let key = |i: usize| (wells[i].well, wells[i].point);
+        for group in order.chunk_by(|&a, &b| key(a) == key(b)) {
+            cells.iter_mut().for_each(String::clear);
+            for well in group.iter().map(|&i| &wells[i]) {
+                let cell = match wavelengths.iter().position(|&w| w == well.wavelength) {
+                    Some(c) => &mut cells[c],
+                    None => continue,
+                };
+                // a duplicate; every value, status included, fills its cell
+                if !cell.is_empty() {
+                    continue;
+                }
+                match well.value {
+                    CellValue::Num(v) => write_num(cell, v, opts.precision)?,
+                    value => cell.push_str(value.status()),
+                }
+            }
+
+            wtr.write_field(&settings.name)?;
+            if opts.source_files {
+                wtr.write_field(&cache.source)?;
+            }
+            let (well, point) = key(group[0]);
+            wtr.write_field(&get_from(&mut cache.wellname, well, WellStrings::from).name)?;
+            if *points {
+                let (r, c) = point.unwrap_or_default();
+                wtr.write_field((r + 1).to_string())?;
+                wtr.write_field((c + 1).to_string())?;
+            }
+            wtr.write_field(&time)?;
+            wtr.write_field(&temp)?;
+            for cell in cells.iter() {
+                wtr.write_field(cell)?;
+            }
+            wtr.write_record(None::<&[u8]>)
+                .context("writing well row")?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.wtr.flush().context("flushing output")
+    }
+
+    fn start_plate(&mut self, settings: &PlateSettings) -> Result<()> {
+        let opts = self.opts;
+        // a column per wavelength, even one listed twice in the settings
+        self.wavelengths.clear();
+        for &wavelength in opts.read_wavelengths(settings).iter() {
+            if opts.keeps_wavelength(wavelength) && !self.wavelengths.contains(&wavelength) {
+                self.wavelengths.push(wavelength);
+            }
+        }
+        self.cells = vec![String::new(); self.wavelengths.len()];
+        self.points = settings.scan_points().is_some_and(|n| n > 1);
+
+        let mut header = vec!["Plate".to_string()];
+        if opts.source_files {
+            header.push("Source File".to_string());
+        }
+        header.push("Well".to_string());
+        if self.points {
+            header.push(Column::PointRow.header(opts).into_owned());
+            header.push(Column::PointCol.header(opts).into_owned());
+        }
+        header.push(opts.time_format.header().to_string());
+        header.push(format!("Temperature [{}]", opts.temp_unit));
+        for &wavelength in &self.wavelengths {
+            header.push(
+                get_from(&mut self.cache.wl, wavelength, WaveStrings::from)
+                    .desc
+                    .clone(),
+            );
+        }
+        self.wtr
+            .write_record(&header)
+            .context("writing well-wide header")
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.cache.source = name.to_string();
+        Ok(())
+    }
+}
+
+/// Writes one parquet record batch per plate read
+#[cfg(feature = "parquet")]
+pub struct ParquetWriter<'a> {
+    wtr: parquet::arrow::ArrowWriter<std::fs::File>,
+    schema: std::sync::Arc<arrow::datatypes::Schema>,
+    cache: Cache,
+    opts: &'a OutputOptions,
+}
+
+#[cfg(feature = "parquet")]
+impl<'a> ParquetWriter<'a> {
+    pub fn new(path: &std::path::Path, opts: &'a OutputOptions) -> Result<Self> {
+        use arrow::datatypes::{DataType, Field, Schema};
+        use parquet::arrow::ArrowWriter;
+        use std::sync::Arc;
+
+        let source = Field::new("source_file", DataType::Utf8, false);
+        let source = Some(source).filter(|_| opts.source_files);
+        let fields = source.into_iter().chain(vec![
+            Field::new("plate", DataType::Utf8, false),
+            Field::new("well", DataType::Utf8, false),
+            Field::new("row", DataType::Int32, false),
+            Field::new("col", DataType::Int32, false),
+            Field::new("time", DataType::Float64, true),
+            Field::new("temperature", DataType::Float64, false),
+            Field::new("read_mode", DataType::Utf8, false),
+            Field::new("excitation", DataType::Utf8, true),
+            Field::new("emission", DataType::Utf8, true),
+            Field::new("description", DataType::Utf8, false),
+            Field::new("value", DataType::Float64, true),
+            Field::new("status", DataType::Utf8, true),
+            Field::new("delay_us", DataType::UInt32, true),
+            Field::new("integration_us", DataType::UInt32, true),
+        ]);
+        let normalized = Field::new("normalized", DataType::Float64, true);
+        let fields = fields.chain(Some(normalized).filter(|_| opts.normalize_to.is_some()));
+        let schema = Arc::new(Schema::new(fields.collect::<Vec<_>>()));
+
+        let out = std::fs::File::create(path).context("creating output parquet file")?;
+        let wtr =
+            ArrowWriter::try_new(out, schema.clone(), None).context("creating parquet writer")?;
+
+        Ok(Self {
+            wtr,
+            schema,
+            cache: Cache::new(),
+            opts,
+        })
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl ReadWriter for ParquetWriter<'_> {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        use arrow::array::{ArrayRef, Float64Builder, Int32Builder, StringBuilder, UInt32Builder};
+        use arrow::record_batch::RecordBatch;
+        use std::sync::Arc;
+
+        let Self {
+            wtr,
+            schema,
+            cache,
+            opts,
+        } = self;
+
+        let mut source = StringBuilder::new();
+        let mut plate = StringBuilder::new();
+        let mut well = StringBuilder::new();
+        let mut row = Int32Builder::new();
+        let mut col = Int32Builder::new();
+        let mut time = Float64Builder::new();
+        let mut temp = Float64Builder::new();
+        let mut mode = StringBuilder::new();
+        let mut ex = StringBuilder::new();
+        let mut em = StringBuilder::new();
+        let mut desc = StringBuilder::new();
+        let mut value = Float64Builder::new();
+        let mut status = StringBuilder::new();
+        let mut delay = UInt32Builder::new();
+        let mut integration = UInt32Builder::new();
+        let mut normalized = Float64Builder::new();
+
+        for w in wells {
+            let strings = get_from(&mut cache.wl, w.wavelength, WaveStrings::from);
+            let name = &get_from(&mut cache.wellname, w.well, WellStrings::from).name;
+            let blank_to_null = |s: &str| Some(s).filter(|s| !s.is_empty()).map(str::to_owned);
+
+            source.append_value(&cache.source);
+            plate.append_value(&settings.name);
+            well.append_value(name);
+            row.append_value(w.well.0 as i32 + 1);
+            col.append_value(w.well.1 as i32 + 1);
+            time.append_option(read_info.get_time().map(R64::raw));
+            temp.append_value(opts.temp_unit.convert(read_info.temp).raw());
+            mode.append_value(read_mode_name(settings, w.wavelength));
+            ex.append_option(blank_to_null(&strings.ex));
+            em.append_option(blank_to_null(&strings.em));
+            desc.append_value(&strings.desc);
+            value.append_option(w.value.num());
+            status.append_option(blank_to_null(w.value.status()));
+            delay.append_option(settings.delay_us());
+            integration.append_option(settings.integration_us());
+            normalized.append_option(w.normalized);
+        }
+
+        let source: Option<ArrayRef> = Some(Arc::new(source.finish()));
+        let source = source.filter(|_| opts.source_files);
+        let columns = source.into_iter().chain(vec![
+            Arc::new(plate.finish()) as ArrayRef,
+            Arc::new(well.finish()),
+            Arc::new(row.finish()),
+            Arc::new(col.finish()),
+            Arc::new(time.finish()),
+            Arc::new(temp.finish()),
+            Arc::new(mode.finish()),
+            Arc::new(ex.finish()),
+            Arc::new(em.finish()),
+            Arc::new(desc.finish()),
+            Arc::new(value.finish()),
+            Arc::new(status.finish()),
+            Arc::new(delay.finish()),
+            Arc::new(integration.finish()),
+        ]);
+        let normalized: Option<ArrayRef> = Some(Arc::new(normalized.finish()));
+        let columns = columns.chain(normalized.filter(|_| opts.normalize_to.is_some()));
+        let batch = RecordBatch::try_new(schema.clone(), columns.collect())
+            .context("building record batch")?;
+        wtr.write(&batch).context("writing record batch")
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.wtr.finish().context("finishing parquet file")?;
+        Ok(())
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.cache.source = name.to_string();
+        Ok(())
+    }
+}
+
+/// Writes a sheet per plate, named after it, with the CSV columns and a frozen header row.
+/// The workbook is held in memory until [`ReadWriter::finish`] saves it
+#[cfg(feature = "xlsx")]
+pub struct XlsxWriter<'a> {
+    workbook: rust_xlsxwriter::Workbook,
+    path: PathBuf,
+    columns: Vec<Column>,
+    /// labels of `columns`
+    headers: Vec<Cow<'a, str>>,
+    header_format: rust_xlsxwriter::Format,
+    /// names of the sheets so far, to keep them unique
+    sheets: Vec<String>,
+    /// next row of the current sheet
+    row: u32,
+    cache: Cache,
+    opts: &'a OutputOptions,
+}
+
+#[cfg(feature = "xlsx")]
+impl<'a> XlsxWriter<'a> {
+    /// Fails if `opts.columns` asks for a column that isn't being written, or `opts.header`
+    /// doesn't label each column
+    pub fn new(path: &Path, opts: &'a OutputOptions) -> Result<Self> {
+        let columns = selected_columns(opts)?;
+        Ok(Self {
+            workbook: rust_xlsxwriter::Workbook::new(),
+            path: path.to_path_buf(),
+            headers: column_headers(opts, &columns)?,
+            columns,
+            header_format: rust_xlsxwriter::Format::new().set_bold(),
+            sheets: Vec::new(),
+            row: 0,
+            cache: Cache::new(),
+            opts,
+        })
+    }
+
+    /// A sheet name from the plate's: Excel allows 31 characters without any of `[]:*?/\`,
+    /// and names must differ ignoring case
+    fn sheet_name(&self, plate: &str) -> String {
+        let base = plate
+            .chars()
+            .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+            .collect::<String>();
+        let base = base.trim_matches('\'');
+        let base = if base.is_empty() { "Plate" } else { base };
+        let taken = |name: &str| self.sheets.iter().any(|s| s.eq_ignore_ascii_case(name));
+
+        (1..)
+            .map(|i| {
+                let suffix = if i == 1 {
+                    String::new()
+                } else {
+                    format!(" ({})", i)
+                };
+                let len = 31 - suffix.chars().count();
+                base.chars().take(len).collect::<String>() + &suffix
+            })
+            .find(|name| !taken(name))
+            .unwrap()
+    }
+}
+
+#[cfg(feature = "xlsx")]
+impl ReadWriter for XlsxWriter<'_> {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        let Self {
+            workbook,
+            columns,
+            sheets,
+            row,
+            cache,
+            opts,
+            ..
+        } = self;
+
+        let sheet = workbook
+            .worksheet_from_index(sheets.len() - 1)
+            .context("finding plate sheet")?;
+        let time = read_info.get_time().map(R64::raw);
+        let temp = opts.temp_unit.convert(read_info.temp).raw();
+        let finite = |n: Option<f64>| n.filter(|n| n.is_finite());
+        // ex/em are blank for absorbance, otherwise whole nm
+        let nm = |s: &str| s.parse::<f64>().ok();
+        let (plate, run) = opts.plate_and_run(&settings.name);
+        let unit = opts
+            .value_unit(settings)
+            .map(OdUnit::name)
+            .unwrap_or_default();
+
+        for well in wells {
+            let strings = get_from(&mut cache.wl, well.wavelength, WaveStrings::from);
+            let names = get_from(&mut cache.wellname, well.well, WellStrings::from);
+            let clock = get_read_time(read_info, &mut cache.time, opts);
+
+            for (c, &column) in columns.iter().enumerate() {
+                let c = c as u16;
+                let (text, number): (&str, Option<f64>) = match column {
+                    Column::Experiment => ("", Some(settings.experiment() as f64)),
+                    Column::Block => ("", Some(settings.block_index() as f64)),
+                    Column::SourceFile => (&cache.source, None),
+                    Column::Plate => (plate, None),
+                    Column::Run => (run, None),
+                    Column::Well => (&names.name, None),
+                    Column::Row => (&names.row, None),
+                    Column::Col => ("", Some(well.well.1 as f64 + 1.0)),
+                    Column::PointRow => ("", well.point.map(|p| p.0 as f64 + 1.0)),
+                    Column::PointCol => ("", well.point.map(|p| p.1 as f64 + 1.0)),
+                    Column::Time => match opts.time_format {
+                        TimeFormat::Hours => ("", time),
+                        TimeFormat::Clock => (clock, None),
+                    },
+                    Column::RawTime => (read_info.raw_time().unwrap_or_default(), None),
+                    Column::Temperature => ("", Some(temp)),
+                    Column::ReadType => (settings.read_type.name(), None),
+                    Column::ReadPattern => {
+                        (settings.read_pattern.as_deref().unwrap_or_default(), None)
+                    }
+                    Column::ReadMode => (read_mode_name(settings, well.wavelength), None),
+                    Column::Excitation => ("", nm(&strings.ex)),
+                    Column::Emission => ("", nm(&strings.em)),
+                    Column::Description => (&strings.desc, None),
+                    Column::Value => ("", well.value.num()),
+                    Column::RawValue => (well.raw.as_deref().unwrap_or_default(), None),
+                    Column::ValueUnit => (unit, None),
+                    Column::Status => (well.value.status(), None),
+                    Column::Delay => ("", settings.delay_us().map(f64::from)),
+                    Column::Integration => ("", settings.integration_us().map(f64::from)),
+                    Column::Normalized => ("", finite(well.normalized)),
+                    Column::CorrectedOd => ("", finite(well.corrected)),
+                    Column::Sample => (
+                        opts.layout
+                            .as_ref()
+                            .map(|l| l.sample(well.well))
+                            .unwrap_or(""),
+                        None,
+                    ),
+                };
+
+                if let Some(n) = number {
+                    sheet.write_number(*row, c, n)?;
+                } else if !text.is_empty() {
+                    sheet.write_string(*row, c, text)?;
+                }
+            }
+            *row += 1;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        // an empty workbook isn't a valid xlsx file
+        if self.sheets.is_empty() {
+            self.workbook.add_worksheet();
+        }
+        self.workbook
+            .save(&self.path)
+            .context("writing output xlsx file")
+    }
+
+    fn start_plate(&mut self, settings: &PlateSettings) -> Result<()> {
+        let name = self.sheet_name(&settings.name);
+        let sheet = self.workbook.add_worksheet();
+        sheet.set_name(&name)?;
+        for (c, header) in self.headers.iter().enumerate() {
+            sheet.write_string_with_format(0, c as u16, header.as_ref(), &self.header_format)?;
+        }
+        sheet.set_freeze_panes(1, 0)?;
+
+        self.sheets.push(name);
+        self.row = 1;
+        Ok(())
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.cache.source = name.to_string();
+        Ok(())
+    }
+}
+
+/// Inserts a row per well into the `readings` table of a new SQLite database. All rows are
+/// inserted in one transaction, which [`ReadWriter::finish`] commits after indexing the
+/// table by (plate, well)
+#[cfg(feature = "sqlite")]
+pub struct SqliteWriter<'a> {
+    conn: rusqlite::Connection,
+    cache: Cache,
+    opts: &'a OutputOptions,
+}
+
+#[cfg(feature = "sqlite")]
+impl<'a> SqliteWriter<'a> {
+    /// Fails if there's already a file at `path`, unless `opts.overwrite`, which replaces it
+    pub fn new(path: &Path, opts: &'a OutputOptions) -> Result<Self> {
+        if opts.overwrite {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).context("replacing output database")
+                }
+                _ => (),
+            }
+        } else if path.exists() {
+            bail!("Output database {} already exists", path.display());
+        }
+        let conn = rusqlite::Connection::open(path).context("creating output database")?;
+        conn.execute_batch(
+            "CREATE TABLE readings (
+                source_file TEXT,
+                plate TEXT NOT NULL,
+                well TEXT NOT NULL,
+                row INTEGER NOT NULL,
+                col INTEGER NOT NULL,
+                time REAL,
+                temp REAL NOT NULL,
+                mode TEXT NOT NULL,
+                ex INTEGER,
+                em INTEGER,
+                description TEXT NOT NULL,
+                value REAL,
+                status TEXT
+            );
+            BEGIN;",
+        )
+        .context("creating readings table")?;
+
+        Ok(Self {
+            conn,
+            cache: Cache::new(),
+            opts,
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl ReadWriter for SqliteWriter<'_> {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        let Self { conn, cache, opts } = self;
+
+        let mut insert = conn.prepare_cached(
+            "INSERT INTO readings VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        )?;
+        let source = Some(cache.source.as_str()).filter(|_| opts.source_files);
+        let time = read_info.get_time().map(R64::raw);
+        let temp = opts.temp_unit.convert(read_info.temp).raw();
+
+        for well in wells {
+            let (r, c) = well.well;
+            let name = &get_from(&mut cache.wellname, well.well, WellStrings::from).name;
+            let desc = &get_from(&mut cache.wl, well.wavelength, WaveStrings::from).desc;
+            let (ex, em) = match well.wavelength {
+                Wavelength::Absorbance(_) | Wavelength::PathCheck(_) => (None, None),
+                Wavelength::Fluorescence(ex, em) | Wavelength::Polarization { ex, em, .. } => {
+                    (Some(ex), Some(em))
+                }
+            };
+            let status = Some(well.value.status()).filter(|s| !s.is_empty());
+
+            insert
+                .execute(rusqlite::params![
+                    source,
+                    settings.name,
+                    name,
+                    r + 1,
+                    c + 1,
+                    time,
+                    temp,
+                    read_mode_name(settings, well.wavelength),
+                    ex,
+                    em,
+                    desc,
+                    well.value.num(),
+                    status,
+                ])
+                .context("inserting output row")?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.conn
+            .execute_batch("CREATE INDEX readings_plate_well ON readings (plate, well); COMMIT;")
+            .context("writing output database")
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.cache.source = name.to_string();
+        Ok(())
+    }
+}
+
+/// Creates the output writer for one file of split output
+pub type WriterFactory<'a> = dyn FnMut(&Path) -> Result<Box<dyn ReadWriter + 'a>> + 'a;
+
+/// Writes each plate block to its own file in a directory, named after the plate (or by a
+/// [`NameTemplate`]), or splits the wells by wavelength (see [`SplitWriter::by_wavelength`]).
+///
+/// Names are made filesystem safe, and a repeated name gets a numeric suffix
+/// (`Plate1_2.csv`) rather than overwriting the earlier file.
+pub struct SplitWriter<'a> {
+    dir: PathBuf,
+    extension: String,
+    new_writer: Box<WriterFactory<'a>>,
+    template: Option<NameTemplate>,
+    current: Option<Box<dyn ReadWriter + 'a>>,
+    /// split by wavelength, giving each plate its own files too if `by_plate`
+    by_wavelength: bool,
+    by_plate: bool,
+    /// with `by_wavelength`, the open file of each wavelength, opened by its first well
+    channels: Vec<Channel<'a>>,
+    used: HashSet<String>,
+    /// current input, passed on to each new file's writer
+    source: String,
+}
+
+/// The output file of one wavelength in a [`SplitWriter`]
+struct Channel<'a> {
+    wavelength: Wavelength,
+    wtr: Box<dyn ReadWriter + 'a>,
+    /// if the current plate has been started in this file yet
+    started: bool,
+}
 
-    let mut wtr = csv::Writer::from_writer(wtr);
-    let mut cache = Cache::new(); // todo: move up to write_csv
+impl<'a> SplitWriter<'a> {
+    /// `new_writer` opens the writer for each file, given its path
+    pub fn new(dir: PathBuf, extension: String, new_writer: Box<WriterFactory<'a>>) -> Self {
+        Self {
+            dir,
+            extension,
+            new_writer,
+            template: None,
+            current: None,
+            by_wavelength: false,
+            by_plate: true,
+            channels: Vec::new(),
+            used: HashSet::new(),
+            source: String::new(),
+        }
+    }
+
+    /// Give each wavelength its own file, named after it (e.g. `600nm` or
+    /// `ex485nm_em535nm`): one per plate and wavelength if `by_plate`, or else one per
+    /// wavelength holding its wells from every plate
+    pub fn by_wavelength(mut self, by_plate: bool) -> Self {
+        self.by_wavelength = true;
+        self.by_plate = by_plate;
+        self
+    }
 
-    wtr.write_record(HEADER)
-        .context("writing output CSV header")?;
+    /// Name each file by `template` rather than just the plate name
+    pub fn with_template(mut self, template: NameTemplate) -> Self {
+        self.template = Some(template);
+        self
+    }
 
-    file.0
-        .into_iter()
-        .try_for_each(|block| write_block(block, &mut wtr, &mut cache))
-        .context("writing CSV data")
+    /// `wavelength` is that of a file of one wavelength
+    fn file_stem(&mut self, settings: &PlateSettings, wavelength: Option<Wavelength>) -> String {
+        let channel = wavelength.map(wavelength_file_stem);
+        let name = match (&self.template, &channel) {
+            (Some(template), _) => template.render(settings, &self.source, channel.as_deref()),
+            (None, Some(channel)) if !self.by_plate => channel.clone(),
+            (None, _) => settings.name.clone(),
+        };
+        // each of a plate's wavelengths needs a name of its own
+        let named = match &self.template {
+            Some(template) => template.has_wavelength(),
+            None => !self.by_plate,
+        };
+        let name = match channel {
+            Some(channel) if !named => format!("{}_{}", name, channel),
+            _ => name,
+        };
+        let base = sanitize_file_name(&name);
+        let mut stem = base.clone();
+        let mut n = 1;
+        while !self.used.insert(stem.to_lowercase()) {
+            n += 1;
+            stem = format!("{}_{}", base, n);
+        }
+        stem
+    }
+
+    fn open(
+        &mut self,
+        settings: &PlateSettings,
+        wavelength: Option<Wavelength>,
+    ) -> Result<Box<dyn ReadWriter + 'a>> {
+        let stem = self.file_stem(settings, wavelength);
+        let path = self.dir.join(format!("{}.{}", stem, self.extension));
+        let mut wtr = (self.new_writer)(&path)
+            .with_context(|| anyhow!("opening output {}", path.display()))?;
+        wtr.start_file(&self.source)?;
+        Ok(wtr)
+    }
+}
+
+impl ReadWriter for SplitWriter<'_> {
+    fn start_plate(&mut self, settings: &PlateSettings) -> Result<()> {
+        if self.by_plate {
+            self.finish()?;
+        }
+        if self.by_wavelength {
+            for channel in &mut self.channels {
+                channel.started = false;
+            }
+            return Ok(());
+        }
+
+        let mut wtr = self.open(settings, None)?;
+        wtr.start_plate(settings)?;
+        self.current = Some(wtr);
+
+        Ok(())
+    }
+
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        if !self.by_wavelength {
+            return self
+                .current
+                .as_mut()
+                .context("no plate started before writing a read")?
+                .write_read(settings, read_info, wells);
+        }
+
+        // the read's wells of each wavelength, in the order the wavelengths first appear
+        let mut split = Vec::<(Wavelength, Vec<WellValue>)>::new();
+        for well in wells {
+            match split.iter_mut().find(|(w, _)| *w == well.wavelength) {
+                Some((_, wells)) => wells.push(well.clone()),
+                None => split.push((well.wavelength, vec![well.clone()])),
+            }
+        }
+
+        for (wavelength, wells) in split {
+            let i = match self
+                .channels
+                .iter()
+                .position(|c| c.wavelength == wavelength)
+            {
+                Some(i) => i,
+                None => {
+                    let wtr = self.open(settings, Some(wavelength))?;
+                    self.channels.push(Channel {
+                        wavelength,
+                        wtr,
+                        started: false,
+                    });
+                    self.channels.len() - 1
+                }
+            };
+            let channel = &mut self.channels[i];
+            if !channel.started {
+                channel.wtr.start_plate(settings)?;
+                channel.started = true;
+            }
+            channel.wtr.write_read(settings, read_info, &wells)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let current = self.current.take();
+        let channels = self.channels.drain(..).map(|c| c.wtr);
+        current
+            .into_iter()
+            .chain(channels)
+            .try_for_each(|mut wtr| wtr.finish())
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.source = name.to_string();
+        // files of one wavelength from every plate stay open across inputs
+        self.channels
+            .iter_mut()
+            .try_for_each(|c| c.wtr.start_file(name))
+    }
+}
+
+/// A file name for the wells of one wavelength, from its description: `600nm`,
+/// `ex485nm_em535nm`, or `ex485nm_em535nm_parallel`
+fn wavelength_file_stem(wavelength: Wavelength) -> String {
+    WaveStrings::from(wavelength)
+        .desc
+        .replace(" / ", "_")
+        .replace("ex ", "ex")
+        .replace("em ", "em")
+        .replace(' ', "_")
+}
+
+/// A file name with placeholders filled in from each plate: `{plate}`, `{mode}` (read
+/// mode), `{block}` (the block's position in its input), `{wavelength}` (all of the
+/// plate's, joined by `_`, or the file's own when split by wavelength), and `{source}`
+/// (the input file name, without its extension)
+#[derive(Debug, Clone)]
+pub struct NameTemplate(Vec<NamePart>);
+
+#[derive(Debug, Clone)]
+enum NamePart {
+    Text(String),
+    Plate,
+    Mode,
+    Block,
+    Wavelength,
+    Source,
+}
+
+impl NameTemplate {
+    /// `channel` is the file's wavelength, when each wavelength has its own
+    fn render(&self, settings: &PlateSettings, source: &str, channel: Option<&str>) -> String {
+        let mut name = String::new();
+        for part in &self.0 {
+            match part {
+                NamePart::Text(text) => name.push_str(text),
+                NamePart::Plate => name.push_str(&settings.name),
+                NamePart::Mode => name.push_str(settings.read_mode.name()),
+                NamePart::Block => name.push_str(&settings.block_index().to_string()),
+                NamePart::Wavelength => match channel {
+                    Some(channel) => name.push_str(channel),
+                    None => {
+                        let wavelengths = settings.wavelengths().iter().map(ToString::to_string);
+                        name.push_str(&wavelengths.collect::<Vec<_>>().join("_"));
+                    }
+                },
+                NamePart::Source => {
+                    let stem = Path::new(source).file_stem().unwrap_or_default();
+                    name.push_str(&stem.to_string_lossy());
+                }
+            }
+        }
+        name
+    }
+
+    fn has_wavelength(&self) -> bool {
+        self.0.iter().any(|p| matches!(p, NamePart::Wavelength))
+    }
+}
+
+/// Fails on an unknown or unclosed placeholder, or a template without any placeholder,
+/// which would give every file the same name
+impl FromStr for NameTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(NamePart::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("Unclosed placeholder in name template: {}", s))?;
+            parts.push(match &rest[start + 1..start + end] {
+                "plate" => NamePart::Plate,
+                "mode" => NamePart::Mode,
+                "block" => NamePart::Block,
+                "wavelength" => NamePart::Wavelength,
+                "source" => NamePart::Source,
+                unk => bail!(
+                    "Unknown name template placeholder {{{}}} (valid: {{plate}}, {{mode}}, {{block}}, {{wavelength}}, {{source}})",
+                    unk
+                ),
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(NamePart::Text(rest.to_string()));
+        }
+
+        if parts.iter().all(|p| matches!(p, NamePart::Text(_))) {
+            bail!("Name template needs at least one placeholder, e.g. {{plate}}");
+        }
+        Ok(Self(parts))
+    }
+}
+
+/// Passes each read on to several writers, e.g. a summary alongside the well output
+pub struct MultiWriter<'a>(Vec<Box<dyn ReadWriter + 'a>>);
+
+impl<'a> MultiWriter<'a> {
+    pub fn new(writers: Vec<Box<dyn ReadWriter + 'a>>) -> Self {
+        Self(writers)
+    }
+}
+
+impl ReadWriter for MultiWriter<'_> {
+    fn start_plate(&mut self, settings: &PlateSettings) -> Result<()> {
+        self.0.iter_mut().try_for_each(|w| w.start_plate(settings))
+    }
+
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        self.0
+            .iter_mut()
+            .try_for_each(|w| w.write_read(settings, read_info, wells))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.0.iter_mut().try_for_each(|w| w.finish())
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.0.iter_mut().try_for_each(|w| w.start_file(name))
+    }
+}
+
+/// Holds every read until finish, then writes its wells to `inner` sorted by plate,
+/// wavelength, time, row, and column, so the output doesn't depend on the export's order.
+/// Each read is passed on as one read per wavelength, so those of a plate can be ordered by
+/// wavelength before time.
+///
+/// Plates with the same name are kept apart, in the order they were read
+pub struct SortWriter<'a> {
+    inner: Box<dyn ReadWriter + 'a>,
+    sources: Vec<String>,
+    plates: Vec<PlateSettings>,
+    reads: Vec<SortedRead>,
+    whole_reads: bool,
+}
+
+/// A read, or the wells of one of its wavelengths, held by a [`SortWriter`]
+struct SortedRead {
+    /// indices of its input and plate
+    source: usize,
+    plate: usize,
+    /// `None` for a whole read
+    wavelength: Option<Wavelength>,
+    info: ReadInfo,
+    wells: Vec<WellValue>,
+}
+
+impl<'a> SortWriter<'a> {
+    pub fn new(inner: Box<dyn ReadWriter + 'a>) -> Self {
+        Self {
+            inner,
+            sources: Vec::new(),
+            plates: Vec::new(),
+            reads: Vec::new(),
+            whole_reads: false,
+        }
+    }
+
+    /// Pass each read on whole instead, sorted by plate and time with its wells sorted by
+    /// wavelength, row, and column; for writers that need all of a read's wavelengths
+    /// together, such as [`MatrixWriter`] and [`WellWideWriter`]
+    pub fn whole_reads(mut self) -> Self {
+        self.whole_reads = true;
+        self
+    }
+}
+
+impl ReadWriter for SortWriter<'_> {
+    fn start_plate(&mut self, settings: &PlateSettings) -> Result<()> {
+        self.plates.push(settings.clone());
+        Ok(())
+    }
+
+    fn write_read(
+        &mut self,
+        _settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        let source = self.sources.len().saturating_sub(1);
+        let plate = self.plates.len().saturating_sub(1);
+        let mut wells = wells.to_vec();
+        wells.sort_by_key(|w| (w.wavelength, w.well, w.point));
+        let read = |wavelength, wells| SortedRead {
+            source,
+            plate,
+            wavelength,
+            info: read_info.clone(),
+            wells,
+        };
+
+        // an empty read is still passed on, to start its plate
+        if self.whole_reads || wells.is_empty() {
+            self.reads.push(read(None, wells));
+        } else {
+            for group in wells.chunk_by(|a, b| a.wavelength == b.wavelength) {
+                self.reads
+                    .push(read(Some(group[0].wavelength), group.to_vec()));
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let Self {
+            inner,
+            sources,
+            plates,
+            reads,
+            ..
+        } = self;
+
+        // stable, so reads at the same time keep their order
+        reads.sort_by(|a, b| {
+            let key = |r: &SortedRead| {
+                let name = &plates[r.plate].name;
+                (name, r.plate, r.wavelength, r.info.get_time())
+            };
+            key(a).cmp(&key(b))
+        });
+
+        let (mut source, mut plate) = (None, None);
+        for read in reads.drain(..) {
+            if source != Some(read.source) {
+                if let Some(name) = sources.get(read.source) {
+                    inner.start_file(name)?;
+                }
+                source = Some(read.source);
+            }
+            let settings = &plates[read.plate];
+            if plate != Some(read.plate) {
+                inner.start_plate(settings)?;
+                plate = Some(read.plate);
+            }
+            inner.write_read(settings, &read.info, &read.wells)?;
+        }
+
+        inner.finish()
+    }
+
+    fn start_file(&mut self, name: &str) -> Result<()> {
+        self.sources.push(name.to_string());
+        Ok(())
+    }
+}
+
+/// One CSV row of plate settings per plate block, without any well values
+pub struct SummaryWriter {
+    wtr: csv::Writer<Box<dyn Write>>,
+}
+
+impl SummaryWriter {
+    /// Writes the header row right away
+    pub fn new(wtr: Box<dyn Write>) -> Result<Self> {
+        let mut wtr = csv::Writer::from_writer(wtr);
+        wtr.write_record([
+            "Plate",
+            "Read Type",
+            "Read Mode",
+            "Plate Size",
+            "Reads",
+            "Rows",
+            "Cols",
+            "Wavelengths",
+        ])
+        .context("writing summary header")?;
+
+        Ok(Self { wtr })
+    }
+}
+
+impl ReadWriter for SummaryWriter {
+    fn start_plate(&mut self, settings: &PlateSettings) -> Result<()> {
+        let wavelengths = settings
+            .wavelengths()
+            .iter()
+            .map(|&w| WaveStrings::from(w).desc)
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        self.wtr
+            .write_record([
+                settings.name.as_str(),
+                settings.read_type.name(),
+                settings.read_mode.name(),
+                &settings.plate_size().to_string(),
+                &settings.reads().to_string(),
+                &settings.rows().to_string(),
+                &settings.cols().to_string(),
+                &wavelengths,
+            ])
+            .context("writing summary row")
+    }
+
+    fn write_read(&mut self, _: &PlateSettings, _: &ReadInfo, _: &[WellValue]) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.wtr.flush().context("flushing summary")
+    }
+}
+
+/// How many wells were written for each plate, and how many empty cells of its read
+/// region were skipped, written to `wtr` on finish
+pub struct WellCountWriter {
+    wtr: Box<dyn Write>,
+    /// (plate name, wells written, empty cells)
+    plates: Vec<(String, usize, usize)>,
+}
+
+impl WellCountWriter {
+    pub fn new(wtr: Box<dyn Write>) -> Self {
+        Self {
+            wtr,
+            plates: Vec::new(),
+        }
+    }
+}
+
+impl ReadWriter for WellCountWriter {
+    fn start_plate(&mut self, settings: &PlateSettings) -> Result<()> {
+        self.plates.push((settings.name.clone(), 0, 0));
+        Ok(())
+    }
+
+    fn write_read(
+        &mut self,
+        _settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        if let Some((_, written, empty)) = self.plates.last_mut() {
+            *written += wells.len();
+            *empty += read_info.empty_cells;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (name, written, empty) in &self.plates {
+            writeln!(
+                self.wtr,
+                "Plate '{}': {} wells, {} empty cells skipped",
+                name, written, empty
+            )?;
+        }
+        self.wtr.flush().context("flushing well counts")
+    }
+}
+
+/// Totals of the reads and wells that would be written, without writing them; see
+/// [`TotalsWriter::write_table`]
+#[derive(Debug, Default)]
+pub struct TotalsWriter {
+    reads: usize,
+    wells: usize,
+    wavelengths: HashSet<Wavelength>,
+    plates: HashSet<String>,
+}
+
+impl TotalsWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the totals as a small table, along with the inputs' `blocks` (which this
+    /// doesn't see, as group and note blocks have no reads)
+    pub fn write_table(&self, mut wtr: impl Write, blocks: usize) -> Result<()> {
+        let rows = [
+            ("blocks", blocks),
+            ("reads", self.reads),
+            ("wells", self.wells),
+            ("wavelengths", self.wavelengths.len()),
+            ("plates", self.plates.len()),
+        ];
+        for (name, count) in rows {
+            writeln!(wtr, "{:<12}{:>10}", name, count)?;
+        }
+        wtr.flush().context("flushing totals")
+    }
+}
+
+impl ReadWriter for TotalsWriter {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        _read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        // a read with all its wells filtered out writes nothing
+        if wells.is_empty() {
+            return Ok(());
+        }
+        self.plates.insert(settings.name.clone());
+        self.reads += 1;
+        self.wells += wells.len();
+        self.wavelengths.extend(wells.iter().map(|w| w.wavelength));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Per-column and whole-plate statistics of each (plate, read, wavelength) that's output.
+///
+/// Only numeric values are counted, and only the wells kept by the output options
+pub struct QcWriter<'a> {
+    wtr: csv::Writer<Box<dyn Write>>,
+    cache: Cache,
+    values: Vec<(u8, f64)>,
+    opts: &'a OutputOptions,
+}
+
+impl<'a> QcWriter<'a> {
+    /// Writes the header row right away
+    pub fn new(wtr: Box<dyn Write>, opts: &'a OutputOptions) -> Result<Self> {
+        let mut wtr = csv::Writer::from_writer(wtr);
+        wtr.write_record([
+            "Plate",
+            opts.time_format.header(),
+            "Wavelength Description",
+            "Col",
+            "N",
+            "Mean",
+            "SD",
+            "CV [%]",
+            "Min",
+            "Max",
+            "Z-Factor",
+        ])
+        .context("writing QC header")?;
+
+        Ok(Self {
+            wtr,
+            cache: Cache::new(),
+            values: Vec::new(),
+            opts,
+        })
+    }
+}
+
+impl ReadWriter for QcWriter<'_> {
+    fn write_read(
+        &mut self,
+        settings: &PlateSettings,
+        read_info: &ReadInfo,
+        wells: &[WellValue],
+    ) -> Result<()> {
+        let Self {
+            wtr,
+            cache,
+            values,
+            opts,
+        } = self;
+
+        let time = get_read_time(read_info, &mut cache.time, opts);
+
+        for &wavelength in opts.read_wavelengths(settings).iter() {
+            // (col, value)
+            values.clear();
+            values.extend(
+                wells
+                    .iter()
+                    .filter(|w| w.wavelength == wavelength)
+                    .filter_map(|w| Some((w.well.1, w.value.num()?))),
+            );
+            if values.is_empty() {
+                continue;
+            }
+            let desc = &get_from(&mut cache.wl, wavelength, WaveStrings::from).desc;
+
+            let mut cols = values.iter().map(|&(c, _)| c).collect::<Vec<_>>();
+            cols.sort_unstable();
+            cols.dedup();
+            for c in cols {
+                let stats = Stats::new(values.iter().filter(|v| v.0 == c).map(|v| v.1));
+                let col = (c as u16 + 1).to_string();
+                wtr.write_record(stats.record(&settings.name, time, desc, &col, None))
+                    .context("writing QC row")?;
+            }
+
+            let stats = Stats::new(values.iter().map(|v| v.1));
+            let z = match (&opts.blank, &opts.positive_control) {
+                (Some(blank), Some(positive)) => {
+                    let group = |sel: &WellSelection| {
+                        Stats::new(
+                            wells
+                                .iter()
+                                .filter(|w| w.wavelength == wavelength && sel.contains(w.well))
+                                .filter_map(|w| w.value.num()),
+                        )
+                    };
+                    Some(z_factor(&group(positive), &group(blank)))
+                }
+                _ => None,
+            };
+            wtr.write_record(stats.record(&settings.name, time, desc, "All", z))
+                .context("writing QC row")?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.wtr.flush().context("flushing QC output")
+    }
+}
+
+/// Summary statistics of a group of values:
+///
+/// - mean = Σx / n
+/// - SD, the sample standard deviation = √(Σ(x - mean)² / (n - 1))
+/// - CV = 100 · SD / mean
+///
+/// SD and CV are undefined (`NaN`) for a single value, and CV for a zero mean
+struct Stats {
+    n: usize,
+    mean: f64,
+    sd: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Stats {
+    fn new<I: Iterator<Item = f64> + Clone>(values: I) -> Self {
+        let (n, sum, min, max) = values.clone().fold(
+            (0, 0.0, f64::INFINITY, f64::NEG_INFINITY),
+            |(n, sum, min, max), v| (n + 1, sum + v, min.min(v), max.max(v)),
+        );
+        let mean = sum / n as f64;
+        let sq_dev = values.map(|v| (v - mean).powi(2)).sum::<f64>();
+        let sd = (sq_dev / (n as f64 - 1.0)).sqrt();
+
+        Self {
+            n,
+            mean,
+            sd,
+            min,
+            max,
+        }
+    }
+
+    fn cv(&self) -> f64 {
+        100.0 * self.sd / self.mean
+    }
+
+    fn record(
+        &self,
+        plate: &str,
+        time: &str,
+        desc: &str,
+        col: &str,
+        z: Option<f64>,
+    ) -> Vec<String> {
+        let stats = [self.mean, self.sd, self.cv(), self.min, self.max];
+        [plate, time, desc, col]
+            .iter()
+            .map(|s| s.to_string())
+            .chain(std::iter::once(self.n.to_string()))
+            .chain(stats.iter().copied().map(fmt_stat))
+            .chain(std::iter::once(z.map(fmt_stat).unwrap_or_default()))
+            .collect()
+    }
+}
+
+/// Z-factor (Zhang et al. 1999) of a positive and negative control:
+///
+/// Z = 1 - 3 · (SDp + SDn) / |meanp - meann|
+///
+/// Blank subtraction shifts both means equally, so it doesn't change Z
+fn z_factor(positive: &Stats, negative: &Stats) -> f64 {
+    1.0 - 3.0 * (positive.sd + negative.sd) / (positive.mean - negative.mean).abs()
+}
+
+/// Blank for undefined values, like the SD of a single well
+fn fmt_stat(v: f64) -> String {
+    if v.is_finite() {
+        v.to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// Replace path separators, control characters, and characters Windows doesn't allow
+fn sanitize_file_name(name: &str) -> String {
+    let name = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+
+    match name.trim_matches('.') {
+        "" => "plate".to_string(),
+        _ => name,
+    }
+}
+
+fn for_each_record<F>(
+    settings: &PlateSettings,
+    read_info: &ReadInfo,
+    wells: &[WellValue],
+    cache: &mut Cache,
+    opts: &OutputOptions,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(&JsonRecord) -> Result<()>,
+{
+    for well in wells {
+        let WellStrings { name, row, col } =
+            get_from(&mut cache.wellname, well.well, WellStrings::from);
+        let desc = &get_from(&mut cache.wl, well.wavelength, WaveStrings::from).desc;
+        let (excitation, emission) = match well.wavelength {
+            Wavelength::Absorbance(_) | Wavelength::PathCheck(_) => (None, None),
+            Wavelength::Fluorescence(ex, em) | Wavelength::Polarization { ex, em, .. } => {
+                (Some(ex), Some(em))
+            }
+        };
+
+        let record = JsonRecord {
+            source_file: Some(cache.source.as_str()).filter(|_| opts.source_files),
+            plate: &settings.name,
+            well: name,
+            row,
+            col,
+            time: read_info.get_time().map(R64::raw),
+            temperature: opts.temp_unit.convert(read_info.temp).raw(),
+            read_mode: read_mode_name(settings, well.wavelength),
+            excitation,
+            emission,
+            description: desc,
+            value: well.value.num(),
+            normalized: opts.normalize_to.as_ref().map(|_| well.normalized),
+            corrected_od: opts.pathlength_k.map(|_| well.corrected),
+            status: Some(well.value.status()).filter(|s| !s.is_empty()),
+            delay_us: settings.delay_us(),
+            integration_us: settings.integration_us(),
+        };
+
+        f(&record)?;
+    }
+
+    Ok(())
+}
+
+/// One well value, with the same fields as a row of the CSV output
+#[derive(Debug, Serialize)]
+struct JsonRecord<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_file: Option<&'a str>,
+    plate: &'a str,
+    well: &'a str,
+    row: &'a str,
+    col: &'a str,
+    time: Option<f64>,
+    temperature: f64,
+    read_mode: &'a str,
+    excitation: Option<u16>,
+    emission: Option<u16>,
+    description: &'a str,
+    value: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    normalized: Option<Option<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    corrected_od: Option<Option<f64>>,
+    status: Option<&'a str>,
+    delay_us: Option<u32>,
+    integration_us: Option<u32>,
+}
+
+/// One row of the CSV (or TSV) output, to read it back with the csv crate's `Deserialize`.
+///
+/// Fields are named after the column headers, whichever `--time-format` or `--temp-unit`
+/// they were written with. Columns that are only written with an option (or left out by
+/// `--columns`) are `None` when missing. [`CsvWriter`] itself writes rows from string
+/// arrays rather than serializing this, which lets `--columns` pick and reorder them and
+/// is over twice as fast (see the `convert` benchmark)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputRecord {
+    #[serde(rename = "Experiment", default)]
+    pub experiment: Option<usize>,
+    #[serde(rename = "Block", default)]
+    pub block: Option<usize>,
+    #[serde(rename = "Source File", default)]
+    pub source_file: Option<String>,
+    #[serde(rename = "Plate")]
+    pub plate: String,
+    #[serde(rename = "Run", default)]
+    pub run: Option<String>,
+    #[serde(rename = "Well")]
+    pub well: String,
+    #[serde(rename = "Row")]
+    pub row: String,
+    #[serde(rename = "Col")]
+    pub col: u16,
+    #[serde(rename = "Point Row", default)]
+    pub point_row: Option<u8>,
+    #[serde(rename = "Point Col", default)]
+    pub point_col: Option<u8>,
+    /// decimal hours or `HH:MM:SS`, as written
+    #[serde(rename = "Time [hr]", alias = "Time [hh:mm:ss]", default)]
+    pub time: Option<String>,
+    #[serde(rename = "Raw Time", default)]
+    pub raw_time: Option<String>,
+    /// in the unit of the header
+    #[serde(
+        rename = "Temperature [C]",
+        alias = "Temperature [F]",
+        alias = "Temperature [K]"
+    )]
+    pub temperature: f64,
+    #[serde(rename = "Read Type", default)]
+    pub read_type: Option<String>,
+    #[serde(rename = "Read Pattern", default)]
+    pub read_pattern: Option<String>,
+    #[serde(rename = "Read Mode")]
+    pub read_mode: String,
+    #[serde(rename = "Excitation [nm]", default)]
+    pub excitation: Option<u16>,
+    #[serde(rename = "Emission [nm]", default)]
+    pub emission: Option<u16>,
+    #[serde(rename = "Wavelength Description")]
+    pub description: String,
+    #[serde(rename = "Value", default)]
+    pub value: Option<f64>,
+    #[serde(rename = "Raw Value", default)]
+    pub raw_value: Option<String>,
+    #[serde(rename = "Value Unit", default)]
+    pub value_unit: Option<String>,
+    #[serde(rename = "Status", default)]
+    pub status: Option<String>,
+    #[serde(rename = "Delay [us]", default)]
+    pub delay_us: Option<u32>,
+    #[serde(rename = "Integration [us]", default)]
+    pub integration_us: Option<u32>,
+    #[serde(rename = "Normalized", default)]
+    pub normalized: Option<f64>,
+    #[serde(rename = "Corrected OD", default)]
+    pub corrected_od: Option<f64>,
+    #[serde(rename = "Sample", default)]
+    pub sample: Option<String>,
 }
 
 #[derive(Debug)]
 struct Cache {
-    wellname: HashMap<WellRC, String>,
+    /// name of the current input, for the Source File column
+    source: String,
+    wellname: HashMap<WellRC, WellStrings>,
     time: HashMap<R64, String>,
     temp: HashMap<R64, String>,
     wl: HashMap<Wavelength, WaveStrings>,
@@ -42,6 +3134,7 @@ struct Cache {
 impl Cache {
     fn new() -> Self {
         Self {
+            source: String::new(),
             wellname: HashMap::with_capacity(384),
             time: HashMap::with_capacity(4),
             temp: HashMap::with_capacity(4),
@@ -58,70 +3151,82 @@ where
     map.entry(key).or_insert_with(|| default(key))
 }
 
-fn write_block<W: Write>(
-    block: PlateBlock,
-    wtr: &mut csv::Writer<W>,
-    cache: &mut Cache,
-) -> Result<()> {
-    let mut value = String::with_capacity(64);
-    let PlateBlock { settings, data } = block;
-
-    for (read_info, wells) in data {
-        for well in wells {
-            let wellname = get_from(&mut cache.wellname, well.well, fmt_wellname);
-            // todo: more than 384 well (AA)?
-            let r = &wellname[..1];
-            let c = wellname[1..].trim_matches('0');
-            let time = get_read_time(&read_info, &mut cache.time);
-            let temp = get_from(&mut cache.temp, read_info.temp, fmt_temp);
-            let WaveStrings { mode, ex, em, desc } =
-                get_from(&mut cache.wl, well.wavelength, WaveStrings::from);
-
-            write!(&mut value, "{}", well.value)?;
-
-            let row: [&str; 11] = [
-                &settings.name,
-                wellname,
-                r,
-                c,
-                time,
-                temp,
-                mode,
-                ex,
-                em,
-                desc,
-                &value,
-            ];
-            wtr.write_record(&row).context("writing output row")?;
+fn get_read_time<'a>(
+    info: &ReadInfo,
+    cache: &'a mut HashMap<R64, String>,
+    opts: &OutputOptions,
+) -> &'a str {
+    info.get_time()
+        .map(move |t| {
+            get_from(cache, t, |t| fmt_time(t, opts.time_format, opts.precision)).as_str()
+        })
+        .unwrap_or("")
+}
 
-            value.clear();
-        }
+/// The Read Mode column, where mP computed from an FP read has its own
+fn read_mode_name(settings: &PlateSettings, wavelength: Wavelength) -> &'static str {
+    match wavelength {
+        Wavelength::Polarization {
+            channel: PolarizationChannel::Millipolarization,
+            ..
+        } => "FP mP",
+        _ => settings.read_mode.name(),
     }
+}
 
-    Ok(())
+#[derive(Debug)]
+struct WellStrings {
+    name: String,
+    row: String,
+    col: String,
 }
 
-fn get_read_time<'a>(info: &ReadInfo, cache: &'a mut HashMap<R64, String>) -> &'a str {
-    info.get_time()
-        .map(move |t| get_from(cache, t, fmt_time).as_str())
-        .unwrap_or("")
+impl From<WellRC> for WellStrings {
+    fn from((r, c): WellRC) -> Self {
+        let row = fmt_row(r);
+        let col = (c as u16 + 1).to_string();
+        let name = well_name((r, c));
+
+        Self { name, row, col }
+    }
 }
 
-fn fmt_wellname(rc: WellRC) -> String {
-    format!("{}{:02}", (b'A' + rc.0) as char, rc.1 + 1)
+/// With `precision` decimal places, or Rust's shortest round-tripping form without
+fn write_num(out: &mut String, v: f64, precision: Option<usize>) -> std::fmt::Result {
+    match precision {
+        Some(precision) => write!(out, "{:.*}", precision, v),
+        None => write!(out, "{}", v),
+    }
 }
 
-fn fmt_temp(temp: R64) -> String {
-    format!("{}", temp)
+fn fmt_num(v: f64, precision: Option<usize>) -> String {
+    let mut out = String::new();
+    // writing to a `String` can't fail
+    let _ = write_num(&mut out, v, precision);
+    out
 }
 
-fn fmt_time(t: R64) -> String {
-    format!("{}", t)
+/// A clock time has as many hour digits as it needs, and a `-` before a negative time
+fn fmt_time(t: R64, format: TimeFormat, precision: Option<usize>) -> String {
+    match format {
+        TimeFormat::Hours => fmt_num(t.raw(), precision),
+        TimeFormat::Clock => {
+            let secs = (t.raw() * 3600.0).round();
+            let sign = if secs < 0.0 { "-" } else { "" };
+            let secs = secs.abs() as u64;
+            format!(
+                "{}{:02}:{:02}:{:02}",
+                sign,
+                secs / 3600,
+                secs / 60 % 60,
+                secs % 60
+            )
+        }
+    }
 }
 
 #[derive(Debug)]
 struct WaveStrings {
-    mode: &'static str,
     ex: Cow<'static, str>,
     em: Cow<'static, str>,
     desc: String,
@@ -129,18 +3234,101 @@ struct WaveStrings {
 
 impl From<Wavelength> for WaveStrings {
     fn from(src: Wavelength) -> Self {
-        let (mode, ex, em, desc) = match src {
-            Wavelength::Absorbance(abs) => {
-                ("Absorbance", "".into(), "".into(), format!("{}nm", abs))
-            }
-            Wavelength::Fluorescence(ex, em) => (
-                "Fluorescence",
-                ex.to_string().into(),
-                em.to_string().into(),
-                format!("ex {}nm / em {}nm", ex, em),
-            ),
+        let (ex, em) = match src {
+            Wavelength::Absorbance(_) | Wavelength::PathCheck(_) => ("".into(), "".into()),
+            Wavelength::Fluorescence(ex, em) | Wavelength::Polarization { ex, em, .. } => {
+                (ex.to_string().into(), em.to_string().into())
+            }
+        };
+        let desc = src.to_string();
+
+        Self { em, ex, desc }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::m5::UniqueReadInfo;
+
+    fn read_at(time: Option<f64>) -> ReadInfo {
+        let unique = match time {
+            Some(t) => UniqueReadInfo::Time(r64(t), String::new()),
+            None => UniqueReadInfo::None,
         };
+        ReadInfo {
+            temp: r64(25.0),
+            unique,
+            empty_cells: 0,
+        }
+    }
+
+    fn time_range(time_min: Option<f64>, time_max: Option<f64>) -> OutputOptions {
+        OutputOptions {
+            time_min,
+            time_max,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn time_range_is_inclusive() {
+        let opts = time_range(Some(0.5), Some(1.5));
+        let kept = [0.25, 0.5, 1.0, 1.5, 1.75].map(|t| opts.keeps_read(&read_at(Some(t))));
+        assert_eq!(kept, [false, true, true, true, false]);
+    }
+
+    #[test]
+    fn reads_without_a_time() {
+        let endpoint = read_at(None);
+        assert!(time_range(None, None).keeps_read(&endpoint));
+        assert!(time_range(None, Some(1.0)).keeps_read(&endpoint));
+        assert!(!time_range(Some(0.0), None).keeps_read(&endpoint));
+        assert!(!time_range(Some(0.0), Some(1.0)).keeps_read(&endpoint));
+    }
+
+    fn value_range(
+        value_min: Option<f64>,
+        value_max: Option<f64>,
+        include_overflow: bool,
+    ) -> OutputOptions {
+        OutputOptions {
+            value_min,
+            value_max,
+            include_overflow,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn value_range_is_inclusive() {
+        let opts = value_range(Some(0.5), Some(1.5), false);
+        let kept = [0.25, 0.5, 1.0, 1.5, 1.75].map(|v| opts.keeps_value(CellValue::Num(v)));
+        assert_eq!(kept, [false, true, true, true, false]);
+        assert!(!opts.keeps_value(CellValue::Masked));
+    }
+
+    /// Overflow counts as above any max
+    #[test]
+    fn value_range_overflow() {
+        let overflow = CellValue::Overflow;
+        assert!(value_range(None, None, false).keeps_value(overflow));
+        assert!(!value_range(Some(0.5), None, false).keeps_value(overflow));
+        assert!(value_range(Some(0.5), None, true).keeps_value(overflow));
+        assert!(!value_range(None, Some(1.5), true).keeps_value(overflow));
+        assert!(!value_range(Some(0.5), Some(1.5), true).keeps_value(overflow));
+    }
 
-        Self { mode, em, ex, desc }
+    #[test]
+    fn clock_time() {
+        let clock = |t| fmt_time(r64(t), TimeFormat::Clock, None);
+        assert_eq!(clock(1.5), "01:30:00");
+        assert_eq!(clock(0.0), "00:00:00");
+        assert_eq!(clock(25.25), "25:15:00");
+        assert_eq!(clock(100.0), "100:00:00");
+        // to the nearest second
+        assert_eq!(clock(1.0 / 3600.0 * 59.6), "00:01:00");
+        assert_eq!(clock(-0.5), "-00:30:00");
+        assert_eq!(clock(-0.0001), "00:00:00");
     }
 }
@@ -5,3 +5,71 @@ where
 {
     r1.and_then(|r1| r2.map(|r2| f(r1, r2)))
 }
+
+/// Match `text` against a glob `pattern`, where `*` matches any run of characters and `?`
+/// any single character
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let p = pattern.chars().collect::<Vec<_>>();
+    let t = text.chars().collect::<Vec<_>>();
+    let (mut pi, mut ti) = (0, 0);
+    // where to retry after the last `*`: (pattern index after it, text index it matched to)
+    let mut star = None;
+
+    while ti < t.len() {
+        match p.get(pi) {
+            Some('*') => {
+                star = Some((pi + 1, ti));
+                pi += 1;
+            }
+            Some(&c) if c == '?' || c == t[ti] => {
+                pi += 1;
+                ti += 1;
+            }
+            _ => match star {
+                Some((sp, st)) => {
+                    pi = sp;
+                    ti = st + 1;
+                    star = Some((sp, st + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Serialize `R64`s as plain `f64`s, since noisy_float doesn't implement serde itself
+#[cfg(feature = "serde")]
+pub(crate) mod r64_serde {
+    use noisy_float::prelude::*;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &R64, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_f64(value.raw())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<R64, D::Error> {
+        let value = f64::deserialize(d)?;
+        R64::try_new(value).ok_or_else(|| D::Error::custom("expected a finite number"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("Plate*", "Plate1"));
+        assert!(glob_match("Plate*", "Plate"));
+        assert!(glob_match("*1", "Plate1"));
+        assert!(glob_match("P?ate#*", "Plate#12"));
+        assert!(glob_match("*a*b*", "xaybz"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("Plate?", "Plate"));
+        assert!(!glob_match("Plate*", "plate1"));
+        assert!(!glob_match("*a*b", "xaybz"));
+        assert!(!glob_match("", "x"));
+    }
+}
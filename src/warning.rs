@@ -0,0 +1,233 @@
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    fmt,
+    io::Write,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Where warnings are reported: stderr as text by default, or a log of JSON lines.
+///
+/// Clones report to the same place, so the [`ParseOptions`](crate::ParseOptions) and
+/// [`OutputOptions`](crate::OutputOptions) of a run can share one
+#[derive(Clone, Default)]
+pub struct WarningSink(Arc<Mutex<SinkState>>);
+
+#[derive(Default)]
+struct SinkState {
+    log: Option<Box<dyn Write + Send>>,
+    /// messages of the warnings already reported by [`WarningSink::warn_once`] for the
+    /// current input
+    reported: BTreeSet<String>,
+}
+
+/// Something worth knowing about the input or the output that isn't an error
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub(crate) enum Warning {
+    UnmatchedPlate {
+        pattern: String,
+    },
+    AssumedOdUnit {
+        plate: String,
+    },
+    MissingBlockEnd {
+        plate: String,
+    },
+    /// a lenient parse kept the blocks before the input ended. `line` counts from 1
+    Truncated {
+        kept: usize,
+        reason: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        line: Option<usize>,
+    },
+    /// `count` values of a read, from `well` on, aren't finite
+    NonFinite {
+        plate: String,
+        read: usize,
+        well: String,
+        wavelength: String,
+        count: usize,
+    },
+    /// `count` wells of a read, from `well` on, overflowed
+    Overflow {
+        plate: String,
+        read: usize,
+        well: String,
+        wavelength: String,
+        count: usize,
+    },
+    DuplicateWell {
+        plate: String,
+        read: usize,
+        well: String,
+        wavelength: String,
+    },
+    /// wells left out of the mP output
+    SkippedMp {
+        plate: String,
+        count: usize,
+    },
+    ZeroReference {
+        plate: String,
+        wavelength: String,
+    },
+    /// a blank or reference (`role`) well left out of its mean
+    ExcludedWell {
+        plate: String,
+        well: String,
+        role: String,
+        status: String,
+    },
+    NoSelectionValues {
+        plate: String,
+        wavelength: String,
+        role: String,
+    },
+    MissingWavelength {
+        wavelength: String,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnmatchedPlate { pattern } => write!(f, "no plate matched --plate {}", pattern),
+            Self::AssumedOdUnit { plate } => write!(
+                f,
+                "plate {} doesn't give its absorbance unit; assuming OD",
+                plate
+            ),
+            Self::MissingBlockEnd { plate } => {
+                write!(f, "missing ~End line after plate {}", plate)
+            }
+            Self::Truncated { kept, reason, .. } => write!(
+                f,
+                "input ended early, keeping the {} complete blocks: {}",
+                kept, reason
+            ),
+            Self::NonFinite {
+                plate,
+                read,
+                well,
+                wavelength,
+                count,
+            } => write!(
+                f,
+                "{} non-finite values (NaN or inf) in read {} of plate {}, starting with well {} ({}); they're output as Non-finite",
+                count, read, plate, well, wavelength
+            ),
+            Self::Overflow {
+                plate,
+                read,
+                well,
+                wavelength,
+                count,
+            } => write!(
+                f,
+                "{} overflowed wells in read {} of plate {}, starting with well {} ({})",
+                count, read, plate, well, wavelength
+            ),
+            Self::DuplicateWell {
+                plate,
+                read,
+                well,
+                wavelength,
+            } => write!(
+                f,
+                "well {} ({}) is listed more than once in read {} of plate {}",
+                well, wavelength, read, plate
+            ),
+            Self::SkippedMp { plate, count } => write!(
+                f,
+                "skipped {} wells of plate {} without numeric parallel and perpendicular values for mP",
+                count, plate
+            ),
+            Self::ZeroReference { plate, wavelength } => write!(
+                f,
+                "reference for {} in plate {} is zero; not normalizing",
+                wavelength, plate
+            ),
+            Self::ExcludedWell {
+                plate,
+                well,
+                role,
+                status,
+            } => write!(
+                f,
+                "{} well {} of plate {} is {}; leaving it out of the {}",
+                role, well, plate, status, role
+            ),
+            Self::NoSelectionValues {
+                plate,
+                wavelength,
+                role,
+            } => write!(f, "no {} values for {} in plate {}", role, wavelength, plate),
+            Self::MissingWavelength { wavelength } => {
+                write!(f, "wavelength {} not found in any plate", wavelength)
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct LogRecord<'a> {
+    #[serde(flatten)]
+    warning: &'a Warning,
+    message: String,
+}
+
+impl WarningSink {
+    /// Write warnings to `log` as JSON lines instead of to stderr as text. Each is an object
+    /// with the warning's `kind`, its `message`, and the fields saying where it happened
+    /// (`plate`, `well`, `line`, ...). A line buffered writer keeps the log current
+    pub fn json(log: Box<dyn Write + Send>) -> Self {
+        Self(Arc::new(Mutex::new(SinkState {
+            log: Some(log),
+            reported: BTreeSet::new(),
+        })))
+    }
+
+    fn state(&self) -> MutexGuard<'_, SinkState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Forget the warnings reported once, so the next input reports them again
+    pub(crate) fn start_input(&self) {
+        self.state().reported.clear();
+    }
+
+    /// [`warn`](Self::warn), unless a warning with the same message has already been
+    /// reported for this input; for warnings about a plate, that's once per plate name
+    pub(crate) fn warn_once(&self, warning: Warning) {
+        let new = self.state().reported.insert(warning.to_string());
+        if new {
+            self.warn(warning);
+        }
+    }
+
+    /// Report `warning` to the warning log, or stderr if there isn't one (or it fails)
+    pub(crate) fn warn(&self, warning: Warning) {
+        let mut state = self.state();
+        if let Some(log) = state.log.as_mut() {
+            let record = LogRecord {
+                warning: &warning,
+                message: warning.to_string(),
+            };
+            let written = serde_json::to_writer(&mut *log, &record)
+                .map_err(Into::into)
+                .and_then(|_| log.write_all(b"\n"));
+            if written.is_ok() {
+                return;
+            }
+        }
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+impl fmt::Debug for WarningSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = self.state().log.is_some();
+        f.debug_struct("WarningSink").field("json", &json).finish()
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+
+use crate::m5::WellRC;
+
+/// Wells selected by a comma-separated list of well names (`A1`, `B02`), rectangular
+/// ranges (`A1-H12`), whole rows (`row:C`), or whole columns (`col:5`)
+#[derive(Debug, Clone, Default)]
+pub struct WellSelection(Vec<WellSpec>);
+
+#[derive(Debug, Copy, Clone)]
+enum WellSpec {
+    Well(WellRC),
+    /// inclusive (top left, bottom right) corners
+    Range(WellRC, WellRC),
+    Row(u8),
+    Col(u8),
+}
+
+impl WellSelection {
+    pub fn contains(&self, (r, c): WellRC) -> bool {
+        self.0.iter().any(|spec| match *spec {
+            WellSpec::Well(rc) => rc == (r, c),
+            WellSpec::Range(start, end) => {
+                (start.0..=end.0).contains(&r) && (start.1..=end.1).contains(&c)
+            }
+            WellSpec::Row(row) => row == r,
+            WellSpec::Col(col) => col == c,
+        })
+    }
+
+    pub fn extend(&mut self, other: Self) {
+        self.0.extend(other.0)
+    }
+}
+
+impl FromStr for WellSelection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+            .map(|spec| parse_spec(spec).with_context(|| anyhow!("Bad well spec: {}", spec)))
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+}
+
+fn parse_spec(spec: &str) -> Result<WellSpec> {
+    if let Some(col) = strip_prefix_ignore_case(spec, "col:") {
+        let col: u16 = col.trim().parse().context("parsing column number")?;
+        return col_index(col).map(WellSpec::Col);
+    }
+    if let Some(row) = strip_prefix_ignore_case(spec, "row:") {
+        return row_index(row.trim()).map(WellSpec::Row);
+    }
+
+    match spec.split_once('-') {
+        Some((start, end)) => {
+            let start = parse_well_name(start)?;
+            let end = parse_well_name(end)?;
+            Ok(WellSpec::Range(
+                (start.0.min(end.0), start.1.min(end.1)),
+                (start.0.max(end.0), start.1.max(end.1)),
+            ))
+        }
+        None => parse_well_name(spec).map(WellSpec::Well),
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    s.get(..prefix.len())
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .map(|_| &s[prefix.len()..])
+}
+
+/// Zero-indexed (row, col) of a well name like `A1`, `A01`, `af48`, or `AF48`; the inverse
+/// of [`well_name`]. All well names given on the command line or in a layout are parsed
+/// by this
+pub fn parse_well_name(s: &str) -> Result<WellRC> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .ok_or_else(|| anyhow!("Missing column number in well {}", s))?;
+    let (row, col) = s.split_at(split);
+
+    let col: u16 = col
+        .parse()
+        .with_context(|| anyhow!("Bad column number in well {}", s))?;
+
+    Ok((row_index(row)?, col_index(col)?))
+}
+
+/// Row letters to a zero-indexed row: A..Z, then AA, AB, ...
+fn row_index(row: &str) -> Result<u8> {
+    let letter = |c: u8| (c.to_ascii_uppercase() - b'A') as u16;
+
+    let idx = match *row.as_bytes() {
+        [a] if a.is_ascii_alphabetic() => letter(a),
+        [a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => {
+            (letter(a) + 1) * 26 + letter(b)
+        }
+        _ => bail!("Bad row name: \"{}\"", row),
+    };
+
+    u8::try_from(idx).map_err(|_| anyhow!("Row out of range: {}", row))
+}
+
+/// One-indexed column number to a zero-indexed column
+fn col_index(col: u16) -> Result<u8> {
+    col.checked_sub(1)
+        .and_then(|c| u8::try_from(c).ok())
+        .ok_or_else(|| anyhow!("Column out of range: {}", col))
+}
+
+/// Row letters: A..Z, then AA, AB, ... for plates with more than 26 rows (1536)
+pub(crate) fn fmt_row(r: u8) -> String {
+    let letter = |i: u8| (b'A' + i) as char;
+
+    if r < 26 {
+        letter(r).to_string()
+    } else {
+        [letter(r / 26 - 1), letter(r % 26)].iter().collect()
+    }
+}
+
+/// Well name with a zero-padded column, e.g. `B07` for `(1, 6)`
+pub fn well_name((r, c): WellRC) -> String {
+    format!("{}{:02}", fmt_row(r), c as u16 + 1)
+}
+
+/// Sample names by well, loaded from a CSV layout with `Well` and `Sample` columns
+#[derive(Debug, Clone, Default)]
+pub struct Layout(HashMap<WellRC, String>);
+
+impl Layout {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let file = File::open(path).context("opening layout file")?;
+        Self::from_reader(file)
+    }
+
+    pub fn from_reader<R: Read>(rdr: R) -> Result<Self> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(rdr);
+
+        let headers = rdr.headers().context("reading layout header")?;
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h.eq_ignore_ascii_case(name))
+                .ok_or_else(|| anyhow!("Missing {} column in layout", name))
+        };
+        let well_col = column("Well")?;
+        let sample_col = column("Sample")?;
+
+        let mut samples = HashMap::new();
+        for record in rdr.records() {
+            let record = record.context("reading layout row")?;
+            let well = record.get(well_col).unwrap_or_default();
+            if well.is_empty() {
+                continue;
+            }
+            let well =
+                parse_well_name(well).with_context(|| anyhow!("Bad well in layout: {}", well))?;
+            let sample = record.get(sample_col).unwrap_or_default();
+            samples.insert(well, sample.to_string());
+        }
+
+        Ok(Self(samples))
+    }
+
+    /// Sample name for the well, or "" if it isn't in the layout
+    pub fn sample(&self, well: WellRC) -> &str {
+        self.0.get(&well).map_or("", String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn well_names() {
+        assert_eq!(parse_well_name("A1").unwrap(), (0, 0));
+        assert_eq!(parse_well_name("b07").unwrap(), (1, 6));
+        assert_eq!(parse_well_name(" H12 ").unwrap(), (7, 11));
+        assert_eq!(parse_well_name("AF48").unwrap(), (31, 47));
+        for bad in ["", "A", "12", "A0", "ABC1", "A1x"] {
+            assert!(parse_well_name(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn well_name_round_trip() {
+        // every well of a 1536-well plate
+        for row in 0..32 {
+            for col in 0..48 {
+                let well = (row, col);
+                assert_eq!(parse_well_name(&well_name(well)).unwrap(), well);
+            }
+        }
+        assert_eq!(well_name((1, 6)), "B07");
+        assert_eq!(well_name((26, 0)), "AA01");
+    }
+
+    #[test]
+    fn selection() {
+        let wells: WellSelection = "A1, c3-b2, row:H, col:12".parse().unwrap();
+        assert!(wells.contains((0, 0)));
+        assert!(wells.contains((1, 1)) && wells.contains((2, 2)) && wells.contains((1, 2)));
+        assert!(wells.contains((7, 3)));
+        assert!(wells.contains((4, 11)));
+        assert!(!wells.contains((0, 1)));
+        assert!(!wells.contains((3, 2)));
+        assert!("A1,col:0".parse::<WellSelection>().is_err());
+    }
+
+    #[test]
+    fn layout() {
+        let csv = "Well,Sample\nA1,blank\n b02 , std 1\n,ignored\n";
+        let layout = Layout::from_reader(csv.as_bytes()).unwrap();
+        assert_eq!(layout.sample((0, 0)), "blank");
+        assert_eq!(layout.sample((1, 1)), "std 1");
+        assert_eq!(layout.sample((2, 2)), "");
+        assert!(Layout::from_reader("Well,Name\nA1,x\n".as_bytes()).is_err());
+    }
+}
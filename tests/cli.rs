@@ -0,0 +1,648 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+fn data_dir() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data"))
+}
+
+/// A scratch output path, unique to the test
+fn out_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+fn gunzip(rdr: impl Read) -> String {
+    let mut text = String::new();
+    flate2::read::GzDecoder::new(rdr)
+        .read_to_string(&mut text)
+        .unwrap();
+    text
+}
+
+/// Run the binary from the fixture directory
+fn m5conv(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_m5conv"))
+        .args(args)
+        .current_dir(data_dir())
+        .output()
+        .unwrap()
+}
+
+/// Stdout of a run that has to succeed
+fn stdout(args: &[&str]) -> String {
+    let out = m5conv(args);
+    assert!(
+        out.status.success(),
+        "m5conv {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&out.stderr)
+    );
+    String::from_utf8(out.stdout).unwrap()
+}
+
+#[test]
+fn sort_keeps_reads_whole() {
+    let out = stdout(&["--sort", "--format", "well-wide", "kinetic.txt", "-"]);
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "Plate,Well,Time [hr],Temperature [C],450nm,600nm");
+    // one row per well of each read, with both wavelengths
+    assert_eq!(lines.len(), 1 + 2 * 6);
+    assert_eq!(lines[1], "Kinetic,B03,0,25,0.012,0.112");
+    assert_eq!(lines[7], "Kinetic,B03,0.025,25.5,1.012,1.112");
+}
+
+/// Plate, wavelength, time, then well
+#[test]
+fn sort_orders_wavelengths_before_times() {
+    let out = stdout(&["--sort", "kinetic.txt", "-"]);
+    let rows = out
+        .lines()
+        .skip(1)
+        .map(|l| {
+            let fields = l.split(',').collect::<Vec<_>>();
+            (
+                fields[4].to_string(),
+                fields[9].to_string(),
+                fields[1].to_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut sorted = rows.clone();
+    sorted.sort_by(|a, b| {
+        let time = |s: &str| s.parse::<f64>().unwrap();
+        (&a.1, time(&a.0), &a.2)
+            .partial_cmp(&(&b.1, time(&b.0), &b.2))
+            .unwrap()
+    });
+    assert_eq!(rows, sorted);
+    assert_eq!(rows[0], ("0".into(), "450nm".into(), "B03".into()));
+    assert_eq!(rows[6], ("0.025".into(), "450nm".into(), "B03".into()));
+    assert_eq!(rows[12], ("0".into(), "600nm".into(), "B03".into()));
+}
+
+/// Run the binary on `input` from stdin
+fn m5conv_from(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_m5conv"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Stdout of a run on `input` that has to succeed
+fn stdout_from(args: &[&str], input: &str) -> String {
+    let out = m5conv_from(args, input);
+    assert!(
+        out.status.success(),
+        "m5conv {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&out.stderr)
+    );
+    String::from_utf8(out.stdout).unwrap()
+}
+
+/// A column per timepoint, left empty where a well has no value
+#[test]
+fn kinetic_wide_timepoints() {
+    let args = ["--format", "kinetic-wide", "--time-format", "clock"];
+    let out = stdout(&[&args[..], &["kinetic4.txt", "-"]].concat());
+    assert_eq!(
+        out,
+        "Plate,Well,Wavelength Description,00:00:00,00:00:30,00:01:00,00:01:30\n\
+         Kinetic4,A01,600nm,0.1,1.1,2.1,3.1\n\
+         Kinetic4,A02,600nm,0.2,1.2,,3.2\n\
+         Kinetic4,A03,600nm,0.3,1.3,2.3,3.3\n"
+    );
+}
+
+#[test]
+fn well_wide_pairs_on_one_row() {
+    let out = stdout(&["--format", "well-wide", "ratio.txt", "-"]);
+    assert_eq!(
+        out,
+        "Plate,Well,Time [hr],Temperature [C],ex 485nm / em 535nm,ex 340nm / em 510nm\n\
+         Ratio,A01,,25,1000,2000\n\
+         Ratio,A02,,25,1001,2001\n"
+    );
+}
+
+/// 600 nm listed twice, so A1 has two 600 nm values
+#[test]
+fn well_wide_keeps_first_duplicate() {
+    let input = "##BLOCKS= 1\n\
+        Plate:\tDup\t1.3\tPlateFormat\tEndpoint\tAbsorbance\tRaw\tFALSE\t1\t\t\t\t\t\t2\t600 600 \t1\t1\t96\t1\t1\tOD\t\n\
+        \tTemperature(°C)\t1\t\t1\t\n\
+        \t25.0\t0.1\t\t0.2\t\n\
+        \n\
+        ~End\n";
+    let out = stdout_from(
+        &["--encoding", "utf-8", "--format", "well-wide", "-", "-"],
+        input,
+    );
+    assert_eq!(
+        out,
+        "Plate,Well,Time [hr],Temperature [C],600nm\nDup,A01,,25,0.1\n"
+    );
+}
+
+/// (well, point row, point col, value) of each row of csv output with point columns
+fn scan_rows(out: &str) -> Vec<(String, String, String, String)> {
+    let mut lines = out.lines();
+    let header = lines.next().unwrap().split(',').collect::<Vec<_>>();
+    let col = |name| header.iter().position(|&h| h == name).unwrap();
+    let cols = [
+        col("Well"),
+        col("Point Row"),
+        col("Point Col"),
+        col("Value"),
+    ];
+    lines
+        .map(|l| {
+            let f = l.split(',').collect::<Vec<_>>();
+            let f = cols.map(|c| f[c].to_string());
+            (f[0].clone(), f[1].clone(), f[2].clone(), f[3].clone())
+        })
+        .collect()
+}
+
+#[test]
+fn scan_point_labels() {
+    let rows = scan_rows(&stdout(&["--scan-points", "wellscan3x3.txt", "-"]));
+    assert_eq!(rows.len(), 6 * 9);
+    let row = |well: &str, r: &str, c: &str, v: &str| (well.into(), r.into(), c.into(), v.into());
+    assert!(rows.contains(&row("A01", "1", "1", "0")));
+    assert!(rows.contains(&row("A02", "3", "1", "1.2")));
+    assert!(rows.contains(&row("B03", "3", "2", "12.21")));
+}
+
+#[test]
+fn scan_points_in_wide_formats() {
+    let out = stdout(&["--format", "well-wide", "wellscan3x3.txt", "-"]);
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[0],
+        "Plate,Well,Point Row,Point Col,Time [hr],Temperature [C],ex 485nm / em 535nm"
+    );
+    assert_eq!(lines.len(), 1 + 6 * 9);
+    assert!(lines.contains(&"Scan,B03,3,2,0,25,12.21"));
+
+    let out = stdout(&["--format", "kinetic-wide", "wellscan3x3.txt", "-"]);
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[0],
+        "Plate,Well,Point Row,Point Col,Wavelength Description,0"
+    );
+    assert_eq!(lines.len(), 1 + 6 * 9);
+    assert!(lines.contains(&"Scan,B03,3,2,ex 485nm / em 535nm,12.21"));
+}
+
+#[test]
+fn matrix_rejects_scan_points() {
+    let out = m5conv(&["--format", "matrix", "wellscan3x3.txt", "-"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("points per well"));
+}
+
+#[test]
+fn gzip_round_trip() {
+    let csv = stdout(&["endpoint.txt", "-"]);
+
+    // implied by the extension
+    let path = out_path("gzip_round_trip.csv.gz");
+    stdout(&["endpoint.txt", path.to_str().unwrap()]);
+    assert_eq!(gunzip(File::open(&path).unwrap()), csv);
+
+    let out = m5conv(&["--gzip", "endpoint.txt", "-"]);
+    assert!(out.status.success());
+    assert_eq!(gunzip(&out.stdout[..]), csv);
+}
+
+/// A failed flush of the buffered output is an error, not lost when the buffer is dropped
+#[cfg(target_os = "linux")]
+#[test]
+fn buffered_write_failure() {
+    for args in [
+        &["endpoint.txt", "/dev/full"][..],
+        &["--buffer-size", "1048576", "endpoint.txt", "/dev/full"],
+    ] {
+        let out = m5conv(args);
+        assert!(!out.status.success());
+        assert!(String::from_utf8_lossy(&out.stderr).contains("No space left on device"));
+    }
+}
+
+#[test]
+fn od_unit_conversion() {
+    let header = "Plate,Well,Time [hr],Temperature [C],600nm\n";
+    let od = format!("{}Abs,A01,,25,0.12\nAbs,A02,,25,0.34\n", header);
+    let milli_od = format!("{}Abs,A01,,25,120\nAbs,A02,,25,340\n", header);
+    let wide = |args: &[&str]| stdout(&[&["--format", "well-wide"], args].concat());
+    assert_eq!(wide(&["od.txt", "-"]), od);
+    assert_eq!(wide(&["--od-unit", "od", "mod.txt", "-"]), od);
+    assert_eq!(wide(&["--od-unit", "mod", "od.txt", "-"]), milli_od);
+}
+
+/// Values are <row index>.<col index>
+#[test]
+fn quadrant_well_names() {
+    let out = stdout(&["--format", "well-wide", "quadrant.txt", "-"]);
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1 + 8 * 12);
+    assert_eq!(lines[1], "Quadrant,I13,,25,8.12");
+    assert_eq!(lines[12], "Quadrant,I24,,25,8.23");
+    assert_eq!(lines[96], "Quadrant,P24,,25,15.23");
+}
+
+#[test]
+fn interleaved_matches_blocked() {
+    let out = stdout(&["interleaved.txt", "-"]);
+    assert_eq!(out, stdout(&["interleaved_blocked.txt", "-"]));
+    assert_eq!(out.lines().count(), 1 + 2 * 6);
+}
+
+/// Wells come out in grid order, so sorted to compare
+#[test]
+fn col_major_matches_row_major() {
+    let args = [
+        "--sort",
+        "--orientation",
+        "col-major",
+        "endpoint_col_major.txt",
+        "-",
+    ];
+    assert_eq!(stdout(&args), stdout(&["--sort", "endpoint.txt", "-"]));
+}
+
+/// The three blocks of one export, then the one of another
+#[test]
+fn concatenated_exports() {
+    let out = stdout(&["--experiment", "--block-index", "concatenated.txt", "-"]);
+    let mut blocks = out
+        .lines()
+        .skip(1)
+        .map(|line| line.splitn(4, ',').take(3).collect::<Vec<_>>().join(","))
+        .collect::<Vec<_>>();
+    blocks.dedup();
+    assert_eq!(
+        blocks,
+        ["1,1,Bare", "1,2,Tab", "1,3,Checksum", "2,1,Kinetic"]
+    );
+    // 2 wells in each of the first export's blocks, 24 values in the second's
+    assert_eq!(out.lines().count(), 1 + 3 * 2 + 24);
+}
+
+#[test]
+fn crlf_line_endings() {
+    let out = stdout(&["kinetic_crlf.txt", "-"]);
+    assert!(!out.contains('\r'));
+    assert_eq!(out, stdout(&["kinetic.txt", "-"]));
+}
+
+/// Values and temperatures
+#[test]
+fn decimal_comma_matches_dot_version() {
+    let out = stdout(&["--decimal-comma", "kinetic_comma.txt", "-"]);
+    assert_eq!(out, stdout(&["kinetic.txt", "-"]));
+    assert!(!m5conv(&["kinetic_comma.txt", "-"]).status.success());
+}
+
+/// The plate name only decodes right as UTF-8
+#[test]
+fn utf8_encoding() {
+    let expected = "Plate,Well,Time [hr],Temperature [C],600nm\n\
+                    Cinética,A01,,25,0.1\n\
+                    Cinética,A02,,25,0.11\n";
+    let wide = |args: &[&str]| stdout(&[&["--format", "well-wide"], args].concat());
+    assert_eq!(wide(&["--encoding", "utf8", "utf8.txt", "-"]), expected);
+
+    let text = std::fs::read_to_string(data_dir().join("utf8.txt")).unwrap();
+    let path = out_path("utf16be.txt");
+    let be = text
+        .encode_utf16()
+        .flat_map(|c| c.to_be_bytes())
+        .collect::<Vec<_>>();
+    std::fs::write(&path, be).unwrap();
+    let path = path.to_str().unwrap();
+    assert_eq!(wide(&["--encoding", "utf-16be", path, "-"]), expected);
+}
+
+/// The OD fixture claiming to be a 100 well plate
+#[test]
+fn plate_size_override() {
+    let data = std::fs::read(data_dir().join("od.txt")).unwrap();
+    let text = encoding_rs::MACINTOSH
+        .decode(&data)
+        .0
+        .replacen("\t96\t", "\t100\t", 1);
+    let out = m5conv_from(&["-", "-"], &text);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Unsupported plate size 100"));
+
+    let out = stdout_from(
+        &["--plate-size", "96", "--format", "well-wide", "-", "-"],
+        &text,
+    );
+    assert!(
+        out.ends_with("Abs,A01,,25,0.12\nAbs,A02,,25,0.34\n"),
+        "{}",
+        out
+    );
+    // A1-A2 fits on a 6 well plate, but not all of the endpoint fixture
+    assert!(m5conv_from(&["--plate-size", "6", "-", "-"], &text)
+        .status
+        .success());
+    let out = m5conv(&["--plate-size", "6", "endpoint.txt", "-"]);
+    assert!(String::from_utf8_lossy(&out.stderr)
+        .contains("Read region (rows 1-8, cols 1-12) doesn't fit on a 6 well plate of 2 x 3"));
+}
+
+#[test]
+fn value_precision() {
+    let data = std::fs::read(data_dir().join("od.txt")).unwrap();
+    let text = encoding_rs::MACINTOSH
+        .decode(&data)
+        .0
+        .replacen("0.340", "123.456", 1);
+    let a02 = |args: &[&str]| {
+        let out = stdout_from(
+            &[&["--format", "well-wide"], args, &["-", "-"]].concat(),
+            &text,
+        );
+        out.lines().nth(2).unwrap().to_string()
+    };
+    assert_eq!(a02(&[]), "Abs,A02,,25,123.456");
+    assert_eq!(a02(&["--precision", "2"]), "Abs,A02,,25.00,123.46");
+}
+
+/// Integer counts stay integral unless a precision is asked for; the raw value is always
+/// as written
+#[test]
+fn integral_values() {
+    let data = std::fs::read(data_dir().join("od.txt")).unwrap();
+    let text =
+        encoding_rs::MACINTOSH
+            .decode(&data)
+            .0
+            .replacen("\t0.120\t0.340", "\t12345\t1.50", 1);
+    let columns = ["--raw-value", "--columns", "well,value,raw_value"];
+    let out = stdout_from(&[&columns[..], &["-", "-"]].concat(), &text);
+    assert_eq!(out, "Well,Value,Raw Value\nA01,12345,12345\nA02,1.5,1.50\n");
+    let out = stdout_from(
+        &[&columns[..], &["--precision", "2", "-", "-"]].concat(),
+        &text,
+    );
+    assert_eq!(
+        out,
+        "Well,Value,Raw Value\nA01,12345.00,12345\nA02,1.50,1.50\n"
+    );
+}
+
+#[test]
+fn header_renames_columns() {
+    let columns = ["--columns", "well,value", "od.txt", "-"];
+    let out = stdout(&columns);
+    let renamed = stdout(&[&["--header", "SampleID,Reading"], &columns[..]].concat());
+    assert_eq!(out, "Well,Value\nA01,0.12\nA02,0.34\n");
+    assert_eq!(
+        renamed.split_once('\n'),
+        Some(("SampleID,Reading", out.split_once('\n').unwrap().1))
+    );
+
+    let out = m5conv(&[&["--header", "SampleID"], &columns[..]].concat());
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr)
+        .contains("--header gives 1 column names, but 2 columns are written"));
+}
+
+#[test]
+fn block_index_counts_blocks() {
+    let out = stdout(&[
+        "--block-index",
+        "--columns",
+        "block,plate,well",
+        "end_markers.txt",
+        "-",
+    ]);
+    assert_eq!(
+        out,
+        "Block,Plate,Well\n1,Bare,A01\n1,Bare,A02\n2,Tab,A01\n2,Tab,A02\n3,Checksum,A01\n\
+         3,Checksum,A02\n"
+    );
+}
+
+#[test]
+fn trf_columns_blank_for_other_modes() {
+    let timing = |file| stdout(&["--columns", "well,mode,delay,integration", file, "-"]);
+    assert_eq!(
+        timing("trf.txt").lines().nth(1),
+        Some("A01,Time Resolved Fluorescence,50,400")
+    );
+    for file in ["od.txt", "ratio.txt", "fp.txt"] {
+        let out = timing(file);
+        assert!(out.lines().skip(1).all(|l| l.ends_with(",,")), "{}", out);
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_needs_overwrite_to_replace() {
+    let path = out_path("overwrite.db");
+    std::fs::write(&path, "not a database").unwrap();
+    let path = path.to_str().unwrap();
+    let out = m5conv(&["--format", "sqlite", "od.txt", path]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("pass --overwrite to replace it"));
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "not a database");
+    stdout(&["--format", "sqlite", "--overwrite", "od.txt", path]);
+    assert!(std::fs::read(path).unwrap().starts_with(b"SQLite format 3"));
+}
+
+/// Reported once per plate name in each input, however many times the plate turns up
+#[test]
+fn assumed_od_unit_warned_once() {
+    let out = m5conv(&["endpoint.txt", "endpoint.txt", "kinetic.txt", "-"]);
+    assert!(out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let warned = |plate| {
+        let warning = format!("plate {} doesn't give its absorbance unit", plate);
+        stderr.matches(&warning).count()
+    };
+    assert_eq!((warned("Endpoint"), warned("Kinetic")), (2, 1));
+}
+
+#[test]
+fn overflow_warning_json() {
+    let data = std::fs::read(data_dir().join("endpoint.txt")).unwrap();
+    let text = encoding_rs::MACINTOSH.decode(&data).0;
+    // A01 and A02
+    let text = text.replacen("\t0.0000\t0.0100", "\tOVRFLW\tOVRFLW", 1);
+    let log = out_path("overflow_warnings.json");
+    let log_arg = log.to_str().unwrap();
+    let out = m5conv_from(
+        &["--warnings-json", log_arg, "--encoding", "utf-8", "-", "-"],
+        &text,
+    );
+    assert!(out.status.success());
+
+    let log = std::fs::read_to_string(&log).unwrap();
+    let lines = log.lines().collect::<Vec<_>>();
+    let overflow = lines
+        .iter()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .filter(|w| w["kind"] == "overflow")
+        .collect::<Vec<_>>();
+    assert_eq!(overflow.len(), 1);
+    let warning = &overflow[0];
+    assert_eq!(warning["plate"], "Endpoint");
+    assert_eq!(warning["well"], "A01");
+    assert_eq!(warning["count"], 2);
+    assert!(warning["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("2 overflowed wells in read 1 of plate Endpoint"));
+}
+
+#[test]
+fn zero_blocks_is_header_only() {
+    let csv = stdout_from(&["--encoding", "utf-8", "-", "-"], "##BLOCKS= 0\n");
+    assert_eq!(csv.lines().count(), 1);
+    assert!(csv.starts_with("Plate,Well,"));
+    let json = stdout_from(
+        &["--format", "json", "--encoding", "utf-8", "-", "-"],
+        "##BLOCKS= 0\n",
+    );
+    assert_eq!(json.trim(), "[]");
+}
+
+/// Checked before any output is written
+#[test]
+fn missing_magic_line() {
+    let out = m5conv_from(&["--encoding", "utf-8", "-", "-"], "Plate:\tPlate1\n");
+    assert!(!out.status.success());
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("reading input stdin"), "{}", stderr);
+    assert!(!stderr.contains("writing output"), "{}", stderr);
+    assert!(
+        stderr.contains("the input starts with \"Plate:"),
+        "{}",
+        stderr
+    );
+}
+
+/// Counting checks the input as converting does
+#[test]
+fn count_missing_magic_line() {
+    let out = m5conv_from(&["--count", "--encoding", "utf-8", "-"], "Plate:\tPlate1\n");
+    assert!(!out.status.success());
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.starts_with("Error: reading input stdin"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("the input starts with \"Plate:"),
+        "{}",
+        stderr
+    );
+}
+
+/// The BOM of UTF-8 input overrides the encoding asked for
+#[test]
+fn bom_overrides_encoding() {
+    let expected =
+        "Plate,Well,Time [hr],Temperature [C],600nm\nBom,A01,,25,0.12\nBom,A02,,25,0.34\n";
+    for encoding in ["macintosh", "macroman", "latin1"] {
+        let args = [
+            "--encoding",
+            encoding,
+            "--format",
+            "well-wide",
+            "bom.txt",
+            "-",
+        ];
+        assert_eq!(stdout(&args), expected, "{}", encoding);
+    }
+
+    let input = std::fs::read_to_string(data_dir().join("bom.txt")).unwrap();
+    assert!(input.starts_with("\u{feff}##BLOCKS= 1\n"));
+    let args = ["--encoding", "macintosh", "--format", "well-wide", "-", "-"];
+    assert_eq!(stdout_from(&args, &input), expected);
+}
+
+#[test]
+fn count_totals() {
+    let out = stdout(&["--count", "kinetic.txt", "endpoint.txt", "ratio.txt"]);
+    assert_eq!(
+        out,
+        "blocks               3\n\
+         reads                4\n\
+         wells              123\n\
+         wavelengths          4\n\
+         plates               3\n"
+    );
+    // a plate whose reads are all filtered out isn't counted
+    let out = stdout(&[
+        "--count",
+        "--wavelength",
+        "450",
+        "kinetic.txt",
+        "endpoint.txt",
+    ]);
+    let counts = out
+        .lines()
+        .map(|l| l.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        counts,
+        [
+            ["blocks", "2"],
+            ["reads", "2"],
+            ["wells", "12"],
+            ["wavelengths", "1"],
+            ["plates", "1"]
+        ]
+    );
+}
+
+#[test]
+fn split_by_needs_a_key() {
+    for split in ["", ","] {
+        let dir = out_path("split_by_nothing");
+        let out = m5conv(&["--split-by", split, "endpoint.txt", dir.to_str().unwrap()]);
+        assert!(!out.status.success());
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains("Nothing to split by"), "{}", stderr);
+    }
+}
+
+/// The header is only written to an empty file
+#[test]
+fn append_writes_one_header() {
+    let out = out_path("append.csv");
+    let out_arg = out.to_str().unwrap();
+    let _ = std::fs::remove_file(&out);
+    stdout(&["endpoint.txt", out_arg]);
+    stdout(&["--append", "endpoint.txt", out_arg]);
+
+    let csv = std::fs::read_to_string(&out).unwrap();
+    let headers = csv.lines().filter(|l| l.starts_with("Plate,")).count();
+    assert_eq!(headers, 1);
+    // 95 wells, twice
+    assert_eq!(csv.lines().count(), 1 + 2 * 95);
+
+    let matrix = m5conv(&["--append", "--format", "matrix", "endpoint.txt", out_arg]);
+    assert!(!matrix.status.success());
+}
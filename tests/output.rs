@@ -0,0 +1,286 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use anyhow::Result;
+use m5conv::{
+    convert, write_stream, OutputOptions, OutputRecord, ParseOptions, PlateSettings, ReadInfo,
+    ReadWriter, TempUnit, TimeFormat, WellValue,
+};
+
+/// A fixture decoded as MacRoman, as the exports are
+fn fixture_text(name: &str) -> String {
+    let data = fs::read(format!(
+        "{}/tests/data/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .unwrap();
+    encoding_rs::MACINTOSH.decode(&data).0.into_owned()
+}
+
+/// Output that can be read back after handing it to a writer
+#[derive(Clone, Default)]
+struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuf {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Counts what it's asked to write
+#[derive(Default)]
+struct Recorder {
+    plates: usize,
+    wells: usize,
+}
+
+impl ReadWriter for Recorder {
+    fn write_read(&mut self, _: &PlateSettings, _: &ReadInfo, wells: &[WellValue]) -> Result<()> {
+        self.wells += wells.len();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn start_plate(&mut self, _: &PlateSettings) -> Result<()> {
+        self.plates += 1;
+        Ok(())
+    }
+}
+
+fn limited(limit: usize) -> OutputOptions {
+    OutputOptions {
+        limit: Some(limit),
+        ..Default::default()
+    }
+}
+
+/// The endpoint fixture followed by a block that fails to parse
+fn broken_second_block() -> String {
+    fixture_text("endpoint.txt").replacen("##BLOCKS= 1", "##BLOCKS= 2", 1) + "Plate:\tBroken\n"
+}
+
+#[test]
+fn limit_zero_writes_nothing() {
+    let mut wtr = Recorder::default();
+    let text = broken_second_block();
+    write_stream(
+        text.as_bytes(),
+        &ParseOptions::default(),
+        &mut wtr,
+        &limited(0),
+    )
+    .unwrap();
+    assert_eq!((wtr.plates, wtr.wells), (0, 0));
+}
+
+/// Never reaching the broken block
+#[test]
+fn limit_stops_streaming_parse() {
+    let text = broken_second_block();
+    let opts = ParseOptions::default();
+    assert!(write_stream(
+        text.as_bytes(),
+        &opts,
+        &mut Recorder::default(),
+        &OutputOptions::default()
+    )
+    .is_err());
+
+    let mut wtr = Recorder::default();
+    write_stream(text.as_bytes(), &opts, &mut wtr, &limited(10)).unwrap();
+    assert_eq!((wtr.plates, wtr.wells), (1, 10));
+}
+
+/// Parsed files have no `PartialEq`, so compared by their debug output
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_round_trip() {
+    use m5conv::M5File;
+
+    let file = M5File::read_and_parse(fixture_text("kinetic.txt").as_bytes()).unwrap();
+    let out = SharedBuf::default();
+    m5conv::write_msgpack(&file, Box::new(out.clone())).unwrap();
+    let back: M5File = rmp_serde::from_slice(&out.0.borrow()).unwrap();
+    assert_eq!(format!("{:?}", back), format!("{:?}", file));
+}
+
+/// The same records as the ndjson output
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_stream_matches_ndjson() {
+    let opts = OutputOptions::default();
+    let parse = || m5conv::M5File::read_and_parse(fixture_text("kinetic.txt").as_bytes()).unwrap();
+    let packed = SharedBuf::default();
+    m5conv::write_msgpack_stream(parse(), Box::new(packed.clone()), &opts).unwrap();
+    let lines = SharedBuf::default();
+    m5conv::write_ndjson(parse(), Box::new(lines.clone()), &opts).unwrap();
+
+    let expected = lines
+        .text()
+        .lines()
+        .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+        .collect::<Vec<_>>();
+    let packed = packed.0.borrow();
+    let mut rdr = &packed[..];
+    let mut records = Vec::new();
+    while !rdr.is_empty() {
+        records.push(rmp_serde::from_read::<_, serde_json::Value>(&mut rdr).unwrap());
+    }
+    assert_eq!(records.len(), 24);
+    assert_eq!(records, expected);
+}
+
+/// A sheet per plate, with a header row then a row per well of each read
+#[cfg(feature = "xlsx")]
+#[test]
+fn xlsx_reads_back() {
+    use calamine::{open_workbook, Data, Reader, Xlsx};
+
+    let file = m5conv::M5File::read_and_parse(fixture_text("kinetic.txt").as_bytes()).unwrap();
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("kinetic.xlsx");
+    m5conv::write_xlsx(file, &path, &OutputOptions::default()).unwrap();
+
+    let mut workbook: Xlsx<_> = open_workbook(&path).unwrap();
+    assert_eq!(workbook.sheet_names(), ["Kinetic"]);
+    let sheet = workbook.worksheet_range("Kinetic").unwrap();
+    // 2 reads of 6 wells at 2 wavelengths
+    assert_eq!(sheet.height(), 1 + 24);
+    let header = sheet.rows().next().unwrap();
+    assert_eq!(header[0], Data::String("Plate".into()));
+    assert_eq!(header[1], Data::String("Well".into()));
+    let col = |name: &str| {
+        header
+            .iter()
+            .position(|h| *h == Data::String(name.into()))
+            .unwrap()
+    };
+    let first = &sheet[1];
+    assert_eq!(first[col("Well")], Data::String("B03".into()));
+    assert_eq!(first[col("Time [hr]")], Data::Float(0.0));
+    assert_eq!(
+        first[col("Wavelength Description")],
+        Data::String("450nm".into())
+    );
+    assert_eq!(first[col("Value")], Data::Float(0.012));
+    assert_eq!(first[col("Status")], Data::Empty);
+    let last = &sheet[24];
+    assert_eq!(last[col("Well")], Data::String("C05".into()));
+    assert_eq!(last[col("Time [hr]")], Data::Float(0.025));
+    assert_eq!(last[col("Value")], Data::Float(1.124));
+}
+
+/// A row per well of each read, and an existing database left alone unless it's to be
+/// replaced
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_reads_back() {
+    let parse = || m5conv::M5File::read_and_parse(fixture_text("kinetic.txt").as_bytes()).unwrap();
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("kinetic.db");
+    fs::write(&path, "not a database").unwrap();
+    let err = m5conv::write_sqlite(parse(), &path, &OutputOptions::default()).unwrap_err();
+    assert!(err.to_string().contains("already exists"), "{}", err);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "not a database");
+
+    let opts = OutputOptions {
+        overwrite: true,
+        ..Default::default()
+    };
+    m5conv::write_sqlite(parse(), &path, &opts).unwrap();
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let count: usize = conn
+        .query_row("SELECT COUNT(*) FROM readings", [], |row| row.get(0))
+        .unwrap();
+    // 2 reads of 6 wells at 2 wavelengths
+    assert_eq!(count, 24);
+    let row = conn
+        .query_row(
+            "SELECT plate, well, row, col, time, temp, description, value, status
+             FROM readings WHERE well = 'C05' AND description = '600nm' AND time > 0",
+            [],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, f64>(4)?,
+                    row.get::<_, f64>(5)?,
+                    row.get::<_, String>(6)?,
+                    row.get::<_, f64>(7)?,
+                    row.get::<_, Option<String>>(8)?,
+                ))
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        row,
+        (
+            "Kinetic".into(),
+            "C05".into(),
+            3,
+            5,
+            0.025,
+            25.5,
+            "600nm".into(),
+            1.124,
+            None
+        )
+    );
+}
+
+/// Optional columns, and the headers that change with the output options, still deserialize
+#[test]
+fn csv_reads_back_as_output_records() {
+    let opts = OutputOptions {
+        block_index: true,
+        time_format: TimeFormat::Clock,
+        temp_unit: TempUnit::Fahrenheit,
+        ..Default::default()
+    };
+    let out = SharedBuf::default();
+    let text = fixture_text("kinetic.txt");
+    convert(
+        text.as_bytes(),
+        out.clone(),
+        &ParseOptions::default(),
+        &opts,
+    )
+    .unwrap();
+
+    let records = csv::Reader::from_reader(out.text().as_bytes())
+        .deserialize()
+        .collect::<Result<Vec<OutputRecord>, _>>()
+        .unwrap();
+    // 2 reads of 6 wells at 2 wavelengths
+    assert_eq!(records.len(), 24);
+    let first = &records[0];
+    assert_eq!(first.block, Some(1));
+    assert_eq!(first.experiment, None);
+    assert_eq!(
+        (first.plate.as_str(), first.well.as_str()),
+        ("Kinetic", "B03")
+    );
+    assert_eq!((first.row.as_str(), first.col), ("B", 3));
+    assert_eq!(first.time.as_deref(), Some("00:00:00"));
+    assert_eq!(first.temperature, 77.0);
+    assert_eq!(first.description, "450nm");
+    assert_eq!(first.value, Some(0.012));
+    assert_eq!(first.status, None);
+    assert_eq!(records[23].time.as_deref(), Some("00:01:30"));
+}
@@ -0,0 +1,638 @@
+use std::fs;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use m5conv::{
+    detect_encoding, CellValue, M5File, OdUnit, ParseOptions, PolarizationChannel, ReadMode,
+    ReadType, TempUnit, WarningSink, Wavelength, WellValue,
+};
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(format!(
+        "{}/tests/data/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .unwrap()
+}
+
+fn parse(name: &str) -> M5File {
+    let data = fixture(name);
+    M5File::from_bytes(&data, detect_encoding(&data)).unwrap()
+}
+
+fn parse_with(name: &str, opts: &ParseOptions) -> M5File {
+    let data = fixture(name);
+    let (text, _, _) = detect_encoding(&data).decode(&data);
+    M5File::read_and_parse_with(text.as_bytes(), opts).unwrap()
+}
+
+/// The value of `well` at `wavelength` in the plate's `read`th read (0-based)
+fn value(file: &M5File, read: usize, well: (u8, u8), wavelength: Wavelength) -> CellValue {
+    let plate = file.plates().next().unwrap();
+    find(&plate.data[read].1, well, wavelength).value
+}
+
+fn find(wells: &[WellValue], well: (u8, u8), wavelength: Wavelength) -> &WellValue {
+    wells
+        .iter()
+        .find(|w| w.well == well && w.wavelength == wavelength)
+        .unwrap_or_else(|| panic!("no value for {:?} at {}", well, wavelength))
+}
+
+#[test]
+fn endpoint_full_grid() {
+    let file = parse("endpoint.txt");
+    let plate = file.plates().next().unwrap();
+    let settings = &plate.settings;
+    assert_eq!(settings.name, "Endpoint");
+    assert_eq!(settings.read_type, ReadType::Endpoint);
+    assert_eq!(settings.read_mode, ReadMode::Absorbance);
+    assert_eq!(settings.plate_size(), 96);
+    assert_eq!((settings.rows(), settings.cols()), (8, 12));
+    assert_eq!(settings.wavelengths(), [Wavelength::Absorbance(600)]);
+
+    let (read_info, wells) = &plate.data[0];
+    assert_eq!(read_info.temp.raw(), 25.0);
+    assert_eq!(read_info.get_time(), None);
+    // B03 is empty, so skipped
+    assert_eq!(wells.len(), 95);
+    assert_eq!(read_info.empty_cells, 1);
+    assert!(wells.iter().all(|w| w.well != (1, 2)));
+    let nm600 = Wavelength::Absorbance(600);
+    assert_eq!(find(wells, (0, 0), nm600).value, CellValue::Num(0.0));
+    assert_eq!(find(wells, (7, 11), nm600).value, CellValue::Num(0.95));
+}
+
+#[test]
+fn endpoint_temperature_in_first_column() {
+    let file = parse("temp_first_col.txt");
+    let (read_info, wells) = &file.plates().next().unwrap().data[0];
+    assert_eq!(read_info.temp.raw(), 31.5);
+    assert_eq!(read_info.get_time(), None);
+    assert_eq!(wells.len(), 2);
+}
+
+#[test]
+fn emit_empty_keeps_masked_wells() {
+    let opts = ParseOptions {
+        emit_empty: true,
+        ..Default::default()
+    };
+    let file = parse_with("endpoint.txt", &opts);
+    let wells = &file.plates().next().unwrap().data[0].1;
+    assert_eq!(wells.len(), 96);
+    assert_eq!(
+        find(wells, (1, 2), Wavelength::Absorbance(600)).value,
+        CellValue::Masked
+    );
+}
+
+/// A grid printing just the read region (B03-C05), numbering only its columns
+#[test]
+fn read_region_grid() {
+    let file = parse("kinetic.txt");
+    let plate = file.plates().next().unwrap();
+    assert_eq!(plate.settings.read_type, ReadType::Kinetic);
+    assert_eq!(plate.settings.reads(), 2);
+    assert_eq!(
+        (plate.settings.row_start(), plate.settings.col_start()),
+        (2, 3)
+    );
+    assert_eq!(plate.data.len(), 2);
+    // 2 rows x 3 cols x 2 wavelengths
+    assert!(plate.data.iter().all(|(_, wells)| wells.len() == 12));
+
+    let (nm450, nm600) = (Wavelength::Absorbance(450), Wavelength::Absorbance(600));
+    assert_eq!(value(&file, 0, (1, 2), nm450), CellValue::Num(0.012));
+    assert_eq!(value(&file, 0, (2, 4), nm450), CellValue::Num(0.024));
+    assert_eq!(value(&file, 0, (1, 2), nm600), CellValue::Num(0.112));
+    assert_eq!(value(&file, 1, (2, 4), nm600), CellValue::Num(1.124));
+}
+
+#[test]
+fn kinetic_times() {
+    let file = parse("kinetic.txt");
+    let plate = file.plates().next().unwrap();
+    let times = plate
+        .data
+        .iter()
+        .map(|(info, _)| info.get_time().unwrap().raw())
+        .collect::<Vec<_>>();
+    assert_eq!(times[0], 0.0);
+    assert!((times[1] - 0.025).abs() < 1e-9);
+    assert_eq!(plate.data[1].0.raw_time(), Some("00:01:30"));
+    assert_eq!(plate.data[1].0.temp.raw(), 25.5);
+}
+
+/// Three grids, each with its time in the first column and followed by a blank line
+/// Rows C-F only, so each read's grid is four lines and a spacer; values are
+/// <read>.<row index><col index>
+#[test]
+fn partial_row_span() {
+    let file = parse("row_span.txt");
+    let plates = file.plates().collect::<Vec<_>>();
+    assert_eq!(plates.len(), 2);
+    let nm600 = Wavelength::Absorbance(600);
+    for (read, (_, wells)) in plates[0].data.iter().enumerate() {
+        assert_eq!(wells.len(), 4 * 12);
+        assert!(wells.iter().all(|w| (2..6).contains(&w.well.0)));
+        let first = read as f64 + 0.2;
+        assert_eq!(find(wells, (2, 0), nm600).value, CellValue::Num(first));
+        assert_eq!(
+            find(wells, (5, 11), nm600).value,
+            CellValue::Num(first + 0.311)
+        );
+    }
+    assert_eq!(plates[1].settings.name, "After");
+    assert_eq!(
+        find(&plates[1].data[0].1, (0, 1), nm600).value,
+        CellValue::Num(0.11)
+    );
+}
+
+#[test]
+fn kinetic_timepoints() {
+    let file = parse("kinetic_timepoints.txt");
+    let plate = file.plates().next().unwrap();
+    assert_eq!(plate.settings.read_type, ReadType::Kinetic);
+    assert_eq!(plate.data.len(), 3);
+
+    let times = plate
+        .data
+        .iter()
+        .map(|(info, _)| info.get_time().unwrap().raw())
+        .collect::<Vec<_>>();
+    assert_eq!(times, [0.0, 0.5, 1.0]);
+    let nm450 = Wavelength::Absorbance(450);
+    for (read, &h12) in [0.95, 1.95, 2.95].iter().enumerate() {
+        assert_eq!(plate.data[read].1.len(), 96);
+        assert_eq!(value(&file, read, (7, 11), nm450), CellValue::Num(h12));
+    }
+}
+
+/// Header `1 1 2 2 3 3`: one value per wavelength for each column
+/// The 977 / 900 nm reference reads after the one sample wavelength get stripes of their own
+#[test]
+fn pathcheck_wavelengths() {
+    let file = parse("pathcheck.txt");
+    let plate = file.plates().next().unwrap();
+    let (nm977, nm900) = (Wavelength::PathCheck(977), Wavelength::PathCheck(900));
+    assert_eq!(
+        plate.settings.wavelengths(),
+        [Wavelength::Absorbance(450), nm977, nm900]
+    );
+    assert_eq!(nm977.to_string(), "977nm PathCheck");
+    let wells = &plate.data[0].1;
+    assert_eq!(wells.len(), 3 * 2);
+    assert_eq!(
+        find(wells, (0, 1), Wavelength::Absorbance(450)).value,
+        CellValue::Num(0.6)
+    );
+    assert_eq!(find(wells, (0, 1), nm977).value, CellValue::Num(0.48));
+    assert_eq!(find(wells, (0, 1), nm900).value, CellValue::Num(0.12));
+}
+
+/// The timepoints fixture started an hour in
+#[test]
+fn relative_time() {
+    let data = fixture("kinetic_timepoints.txt");
+    let text = encoding_rs::MACINTOSH.decode(&data).0;
+    let text = text
+        .replacen("01:00:00", "02:00:00", 1)
+        .replacen("00:30:00", "01:30:00", 1)
+        .replacen("00:00:00", "01:00:00", 1);
+    let times = |relative_time| {
+        let opts = ParseOptions {
+            relative_time,
+            ..Default::default()
+        };
+        let file = M5File::read_and_parse_with(text.as_bytes(), &opts).unwrap();
+        let plate = file.plates().next().unwrap();
+        plate
+            .data
+            .iter()
+            .map(|(info, _)| info.get_time().unwrap().raw())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(times(false), [1.0, 1.5, 2.0]);
+    assert_eq!(times(true), [0.0, 0.5, 1.0]);
+}
+
+#[test]
+fn interleaved_grid() {
+    let file = parse("interleaved.txt");
+    let (nm450, nm600) = (Wavelength::Absorbance(450), Wavelength::Absorbance(600));
+    assert_eq!(file.plates().next().unwrap().data[0].1.len(), 12);
+    assert_eq!(value(&file, 0, (0, 0), nm450), CellValue::Num(0.0));
+    assert_eq!(value(&file, 0, (0, 0), nm600), CellValue::Num(1.0));
+    assert_eq!(value(&file, 0, (1, 2), nm450), CellValue::Num(0.12));
+    assert_eq!(value(&file, 0, (1, 2), nm600), CellValue::Num(1.12));
+}
+
+#[test]
+fn fluorescence_polarization_channels() {
+    let file = parse("fp.txt");
+    let plate = file.plates().next().unwrap();
+    assert_eq!(plate.settings.read_mode, ReadMode::FluorescencePolarization);
+    let channel = |channel| Wavelength::Polarization {
+        ex: 485,
+        em: 535,
+        channel,
+    };
+    let (parallel, perpendicular) = (
+        channel(PolarizationChannel::Parallel),
+        channel(PolarizationChannel::Perpendicular),
+    );
+    assert_eq!(plate.settings.wavelengths(), [parallel, perpendicular]);
+    assert_eq!(value(&file, 0, (0, 1), parallel), CellValue::Num(101.0));
+    assert_eq!(
+        value(&file, 0, (0, 1), perpendicular),
+        CellValue::Num(201.0)
+    );
+    assert_eq!(
+        value(&file, 0, (1, 0), perpendicular),
+        CellValue::Num(210.0)
+    );
+}
+
+/// An absorbance and a fluorescence read of one plate, in one block
+#[test]
+fn block_sections() {
+    let file = parse("sections.txt");
+    assert_eq!(file.blocks.len(), 1);
+    let sections = file.plates().collect::<Vec<_>>();
+    let modes = sections
+        .iter()
+        .map(|p| (p.settings.section(), p.settings.read_mode))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        modes,
+        [(1, ReadMode::Absorbance), (2, ReadMode::Fluorescence)]
+    );
+    assert_eq!(
+        find(&sections[0].data[0].1, (0, 1), Wavelength::Absorbance(600)).value,
+        CellValue::Num(0.11)
+    );
+    let fl = Wavelength::Fluorescence(485, 535);
+    assert_eq!(
+        find(&sections[1].data[0].1, (0, 1), fl).value,
+        CellValue::Num(1001.0)
+    );
+}
+
+#[test]
+fn ex_em_count_mismatch() {
+    let data = fixture("ex_em_mismatch.txt");
+    let err = M5File::from_bytes(&data, detect_encoding(&data)).unwrap_err();
+    let err = format!("{:#}", anyhow::Error::new(err));
+    assert!(
+        err.contains(
+            "Expected 3 ex/em wavelength pairs, but found 3 excitation and 2 emission wavelengths"
+        ),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn time_resolved_timing() {
+    let file = parse("trf.txt");
+    let plate = file.plates().next().unwrap();
+    assert_eq!(plate.settings.read_mode, ReadMode::TimeResolved);
+    assert_eq!(plate.settings.delay_us(), Some(50));
+    assert_eq!(plate.settings.integration_us(), Some(400));
+    let wavelength = Wavelength::Fluorescence(340, 615);
+    assert_eq!(plate.settings.wavelengths(), [wavelength]);
+    assert_eq!(value(&file, 0, (0, 2), wavelength), CellValue::Num(1002.0));
+}
+
+#[test]
+fn well_scan_single_point() {
+    let file = parse("wellscan.txt");
+    let plate = file.plates().next().unwrap();
+    let settings = &plate.settings;
+    assert_eq!(settings.read_type, ReadType::WellScan);
+    assert_eq!(settings.read_pattern.as_deref(), Some("Fill"));
+    assert_eq!(settings.scan_points(), Some(1));
+
+    let wells = &plate.data[0].1;
+    assert_eq!(wells.len(), 4);
+    let well = find(wells, (1, 0), Wavelength::Fluorescence(485, 535));
+    assert_eq!(well.value, CellValue::Num(2.5));
+    assert_eq!(well.point, Some((0, 0)));
+}
+
+/// The same export, with the degree sign of its temperature header written by each of the
+/// encodings Softmax uses
+#[test]
+fn temperature_header_encodings() {
+    let macroman = fixture("endpoint.txt");
+    let reencode = |degree: &[u8]| {
+        let at = macroman.iter().position(|&b| b == 0xA1).unwrap();
+        [&macroman[..at], degree, &macroman[at + 1..]].concat()
+    };
+
+    for (data, encoding) in [
+        (macroman.clone(), encoding_rs::MACINTOSH),
+        (reencode(&[0xB0]), encoding_rs::WINDOWS_1252),
+        (reencode(&[0xC2, 0xB0]), encoding_rs::UTF_8),
+    ] {
+        assert_eq!(detect_encoding(&data), encoding);
+        let file = M5File::from_bytes(&data, detect_encoding(&data)).unwrap();
+        assert_eq!(file.plates().next().unwrap().data[0].1.len(), 95);
+    }
+}
+
+/// The same export written as UTF-8 and as Windows-1252, told apart by the degree sign
+#[test]
+fn detected_encodings() {
+    for (name, encoding) in [
+        ("utf8.txt", encoding_rs::UTF_8),
+        ("latin1.txt", encoding_rs::WINDOWS_1252),
+    ] {
+        assert_eq!(detect_encoding(&fixture(name)), encoding, "{}", name);
+        let file = parse(name);
+        let plate = file.plates().next().unwrap();
+        assert_eq!(plate.settings.name, "Cinética", "{}", name);
+        assert_eq!(
+            value(&file, 0, (0, 1), Wavelength::Absorbance(600)),
+            CellValue::Num(0.11)
+        );
+    }
+}
+
+#[test]
+fn unknown_degree_sign_is_an_error() {
+    let data = fixture("endpoint.txt");
+    let text = encoding_rs::MACINTOSH.decode(&data).0.replace('°', "?");
+    let err = M5File::read_and_parse(text.as_bytes()).unwrap_err();
+    assert!(format!("{:#}", anyhow::Error::new(err)).contains("check the input text encoding"));
+}
+
+#[test]
+fn spectrum() {
+    let file = parse("spectrum.txt");
+    let plate = file.plates().next().unwrap();
+    assert_eq!(plate.settings.read_type, ReadType::Spectrum);
+    let nm = |nm| Wavelength::Absorbance(nm);
+    assert_eq!(plate.settings.wavelengths(), [nm(400), nm(420), nm(440)]);
+    // 2 x 3 wells at each of 3 wavelengths
+    assert_eq!(plate.data[0].1.len(), 18);
+    assert_eq!(value(&file, 0, (0, 0), nm(400)), CellValue::Num(0.4));
+    assert_eq!(value(&file, 0, (1, 2), nm(440)), CellValue::Num(5.44));
+}
+
+/// Blank fields after the read region's wells aren't wells of their own
+#[test]
+fn spectrum_ignores_trailing_fields() {
+    let opts = ParseOptions {
+        emit_empty: true,
+        ..Default::default()
+    };
+    let file = parse_with("spectrum.txt", &opts);
+    let wells = &file.plates().next().unwrap().data[0].1;
+    assert_eq!(wells.len(), 18);
+    assert!(wells.iter().all(|w| w.well.0 < 2 && w.well.1 < 3));
+}
+
+#[test]
+fn spectrum_without_wells_is_an_error() {
+    let data = fixture("spectrum.txt");
+    let text = encoding_rs::MACINTOSH.decode(&data).0;
+    let text = text.replace("\t1\t3\t96\t", "\t1\t0\t96\t");
+    let err = M5File::read_and_parse(text.as_bytes()).unwrap_err();
+    assert!(format!("{:#}", anyhow::Error::new(err)).contains("has no wells"));
+}
+
+/// Each well printed as a 3 x 3 block of points
+#[test]
+fn well_scan_points() {
+    let file = parse("wellscan3x3.txt");
+    let plate = file.plates().next().unwrap();
+    assert_eq!(plate.settings.scan_points(), Some(3));
+    let wells = &plate.data[0].1;
+    assert_eq!(wells.len(), 6 * 9);
+
+    let wavelength = Wavelength::Fluorescence(485, 535);
+    let at = |well, point| {
+        wells
+            .iter()
+            .find(|w| w.well == well && w.point == Some(point) && w.wavelength == wavelength)
+            .unwrap()
+            .value
+    };
+    assert_eq!(at((0, 0), (0, 0)), CellValue::Num(0.0));
+    assert_eq!(at((0, 1), (2, 0)), CellValue::Num(1.2));
+    assert_eq!(at((1, 2), (2, 1)), CellValue::Num(12.21));
+}
+
+#[test]
+fn absorbance_units() {
+    let nm600 = Wavelength::Absorbance(600);
+    for (name, unit, a2) in [
+        ("od.txt", OdUnit::Od, 0.34),
+        ("mod.txt", OdUnit::MilliOd, 340.0),
+        // no unit given
+        ("endpoint.txt", OdUnit::Od, 0.01),
+    ] {
+        let file = parse(name);
+        let plate = file.plates().next().unwrap();
+        assert_eq!(plate.settings.od_unit(), Some(unit), "{}", name);
+        assert_eq!(
+            value(&file, 0, (0, 1), nm600),
+            CellValue::Num(a2),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn end_marker_trailing_fields() {
+    let file = parse("end_markers.txt");
+    let plates = file.plates().collect::<Vec<_>>();
+    let ends = plates
+        .iter()
+        .map(|p| (p.settings.name.as_str(), p.settings.end_fields()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ends,
+        [("Bare", None), ("Tab", None), ("Checksum", Some("3F2A"))]
+    );
+    let nm600 = Wavelength::Absorbance(600);
+    for (plate, a2) in plates.iter().zip([0.11, 0.21, 0.31]) {
+        assert_eq!(
+            find(&plate.data[0].1, (0, 1), nm600).value,
+            CellValue::Num(a2)
+        );
+    }
+}
+
+#[test]
+fn preamble_metadata() {
+    let file = parse("preamble.txt");
+    assert_eq!(
+        file.metadata(),
+        [
+            "SoftMax Pro 5.4.1\tSpectraMax M5",
+            "Read date:\t2021-03-04 10:15"
+        ]
+    );
+    let plate = file.plates().next().unwrap();
+    assert_eq!(plate.settings.name, "Preamble");
+    assert!(parse("endpoint.txt").metadata().is_empty());
+}
+
+/// A MacRoman export in memory, with `\xA1` the degree sign
+#[test]
+fn from_inline_bytes() {
+    let data: &[u8] = b"##BLOCKS= 1\n\
+        Plate:\tInline\t1.3\tPlateFormat\tEndpoint\tAbsorbance\tRaw\tFALSE\t1\t\t\t\t\t\t\
+        1\t600 \t1\t2\t96\t1\t1\tOD\n\
+        \tTemperature(\xA1F)\t1\t2\t\n\
+        \t77.0\t0.120\t0.340\t\n\
+        \n\
+        ~End\n";
+    let file = M5File::from_bytes(data, encoding_rs::MACINTOSH).unwrap();
+    let plate = file.plates().next().unwrap();
+    assert_eq!(plate.settings.name, "Inline");
+    assert_eq!(plate.settings.temp_unit(), TempUnit::Fahrenheit);
+    assert_eq!(plate.data[0].0.temp.raw(), 25.0);
+    let nm600 = Wavelength::Absorbance(600);
+    assert_eq!(value(&file, 0, (0, 1), nm600), CellValue::Num(0.34));
+}
+
+/// A01 and A02 of the OD fixture replaced with `cells`
+fn od_with(cells: &str, opts: &ParseOptions) -> Result<M5File, m5conv::M5Error> {
+    let data = fixture("od.txt");
+    let text = encoding_rs::MACINTOSH.decode(&data).0;
+    let text = text.replacen("\t0.120\t0.340", cells, 1);
+    M5File::read_and_parse_with(text.as_bytes(), opts)
+}
+
+#[test]
+fn non_finite_values() {
+    let log = SharedLog::default();
+    let opts = ParseOptions {
+        warnings: WarningSink::json(Box::new(log.clone())),
+        ..Default::default()
+    };
+    let file = od_with("\tNaN\t-inf", &opts).unwrap();
+    let nm600 = Wavelength::Absorbance(600);
+    assert!(matches!(value(&file, 0, (0, 0), nm600), CellValue::NonFinite(v) if v.is_nan()));
+    assert_eq!(
+        value(&file, 0, (0, 1), nm600),
+        CellValue::NonFinite(f64::NEG_INFINITY)
+    );
+    let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(log.lines().count(), 1);
+    assert!(log.contains("\"count\":2"), "{}", log);
+
+    let strict = ParseOptions {
+        strict_values: true,
+        ..Default::default()
+    };
+    let err = od_with("\t0.120\tinf", &strict).unwrap_err();
+    let err = format!("{:#}", anyhow::Error::new(err));
+    assert!(
+        err.contains("Well A02 (600nm) has a non-finite value"),
+        "{}",
+        err
+    );
+}
+
+/// Whitespace is as empty as no cell at all
+#[test]
+fn whitespace_only_cell() {
+    let file = od_with("\t  \t0.340", &ParseOptions::default()).unwrap();
+    let wells = &file.plates().next().unwrap().data[0].1;
+    assert_eq!(wells.len(), 1);
+    assert_eq!(wells[0].well, (0, 1));
+
+    let opts = ParseOptions {
+        emit_empty: true,
+        ..Default::default()
+    };
+    let file = od_with("\t \t0.340", &opts).unwrap();
+    let nm600 = Wavelength::Absorbance(600);
+    assert_eq!(value(&file, 0, (0, 0), nm600), CellValue::Masked);
+}
+
+#[test]
+fn zero_blocks() {
+    let file = M5File::read_and_parse("##BLOCKS= 0\n".as_bytes()).unwrap();
+    assert!(file.blocks.is_empty());
+}
+
+#[test]
+fn missing_magic_shows_first_line() {
+    let err = M5File::read_and_parse("\nPlate:\tPlate1\n".as_bytes()).unwrap_err();
+    let err = format!("{:#}", anyhow::Error::new(err));
+    assert!(err.contains("the input starts with \"Plate:"), "{}", err);
+    let err = M5File::read_and_parse("".as_bytes()).unwrap_err();
+    assert!(format!("{:#}", anyhow::Error::new(err)).contains("the input is empty"));
+}
+
+/// Reads just the lines up to the magic, so they can be parsed along with the rest
+#[test]
+fn read_to_blocks_magic_line() {
+    let text = "Exported from SoftMax Pro\r\n##BLOCKS= 1\nPlate:\tPlate1\n";
+    let mut rdr = text.as_bytes();
+    let start = m5conv::read_to_blocks_magic(&mut rdr).unwrap();
+    assert_eq!(start, "Exported from SoftMax Pro\r\n##BLOCKS= 1\n");
+    assert_eq!(rdr, b"Plate:\tPlate1\n");
+    assert!(m5conv::read_to_blocks_magic(&mut "Plate:\n".as_bytes()).is_err());
+}
+
+/// Parsed files have no `PartialEq`, so compared by their debug output
+#[cfg(feature = "serde")]
+#[test]
+fn serde_json_round_trip() {
+    for name in [
+        "kinetic.txt",
+        "fp.txt",
+        "end_markers.txt",
+        "preamble.txt",
+        "sections.txt",
+    ] {
+        let file = parse(name);
+        let json = serde_json::to_string(&file).unwrap();
+        let back: M5File = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{:?}", back), format!("{:?}", file), "{}", name);
+    }
+}
+
+/// A warning log that can be read back after handing it to a [`WarningSink`]
+#[derive(Clone, Default)]
+struct SharedLog(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A warning given once per plate is given again for the next input parsed with the same
+/// options
+#[test]
+fn warnings_once_per_parse() {
+    let log = SharedLog::default();
+    let opts = ParseOptions {
+        warnings: WarningSink::json(Box::new(log.clone())),
+        ..Default::default()
+    };
+    parse_with("endpoint.txt", &opts);
+    parse_with("endpoint.txt", &opts);
+
+    let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+    let assumed = log
+        .lines()
+        .filter(|l| l.contains(r#""kind":"assumed-od-unit""#))
+        .count();
+    assert_eq!(assumed, 2);
+}